@@ -0,0 +1,390 @@
+use crate::gm::{drum_name, DRUM_CHANNEL};
+use crate::header::TimeScale;
+use crate::track::event::{Event, Key, KeySignature, Tempo};
+use crate::SMF;
+use std::collections::{HashMap, HashSet};
+
+impl SMF {
+    /// Lists the programs used on each of the 16 channels, in order of appearance across all tracks.
+    pub fn programs_per_channel(&self) -> [Vec<u8>; 16] {
+        let mut changes = Vec::new();
+        for track in &self.tracks {
+            for (tick, event) in track.iter_absolute() {
+                if let Event::ProgramChange(p) = event {
+                    changes.push((tick, *p));
+                }
+            }
+        }
+        // Stable sort, so events at the same tick stay in track order
+        changes.sort_by_key(|(tick, _)| *tick);
+        let mut programs: [Vec<u8>; 16] = Default::default();
+        for (_, change) in changes {
+            programs[usize::from(change.channel & 0x0F)].push(change.program);
+        }
+        programs
+    }
+
+    /// Lists the Markers of every track with their absolute time in ticks, sorted by time. Useful to find the sections of a song.
+    /// Cue Points can be listed along with them.
+    pub fn markers(&self, include_cue_points: bool) -> Vec<(u64, String)> {
+        let mut markers = Vec::new();
+        for track in &self.tracks {
+            for (tick, event) in track.iter_absolute() {
+                match event {
+                    Event::Marker(m) => markers.push((tick, m.text.clone())),
+                    Event::CuePoint(c) if include_cue_points => {
+                        markers.push((tick, c.text.clone()))
+                    }
+                    _ => {}
+                }
+            }
+        }
+        markers.sort_by_key(|(tick, _)| *tick);
+        markers
+    }
+
+    /// Lists the controllers used on each of the 16 channels, e.g. to tell if a file uses the sustain pedal (64) or expression (11).
+    pub fn controllers_per_channel(&self) -> [HashSet<u8>; 16] {
+        let mut controllers: [HashSet<u8>; 16] = Default::default();
+        for (_, _, event) in self.iter_absolute() {
+            if let Event::ControllerChange(c) = event {
+                controllers[usize::from(c.channel & 0x0F)].insert(c.controller_number);
+            }
+        }
+        controllers
+    }
+
+    /// Lists the channels used by MIDI Channel Events in the whole file, e.g. {0, 1, 9}.
+    pub fn used_channels(&self) -> HashSet<u8> {
+        self.used_channels_per_track()
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+
+    /// Lists the channels used by MIDI Channel Events in each track.
+    pub fn used_channels_per_track(&self) -> Vec<HashSet<u8>> {
+        self.tracks
+            .iter()
+            .map(|track| {
+                track
+                    .track_events
+                    .iter()
+                    .filter_map(|t| t.event.channel())
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Finds the key of the piece: the Key Signature in effect for the longest time, the first one winning ties.
+    /// Returns None if there is no Key Signature, as a missing one does not mean C major.
+    pub fn primary_key_signature(&self) -> Option<KeySignature> {
+        let mut changes = Vec::new();
+        for track in &self.tracks {
+            for (tick, event) in track.iter_absolute() {
+                if let Event::KeySignature(k) = event {
+                    changes.push((tick, *k));
+                }
+            }
+        }
+        changes.sort_by_key(|(tick, _)| *tick);
+        let end = self.length_ticks();
+        // Total time spent in each key, in order of first appearance
+        let mut durations: Vec<(KeySignature, u64)> = Vec::new();
+        for (i, (tick, key)) in changes.iter().enumerate() {
+            let next = changes.get(i + 1).map(|(t, _)| *t).unwrap_or(end);
+            let duration = next.saturating_sub(*tick);
+            match durations.iter_mut().find(|(k, _)| k == key) {
+                Some((_, total)) => *total += duration,
+                None => durations.push((*key, duration)),
+            }
+        }
+        let mut primary: Option<(KeySignature, u64)> = None;
+        for (key, duration) in durations {
+            match primary {
+                Some((_, longest)) if longest >= duration => {}
+                _ => primary = Some((key, duration)),
+            }
+        }
+        primary.map(|(key, _)| key)
+    }
+
+    /// Guesses the key of the piece from its notes, for files without a Key Signature.
+    /// This is a heuristic: the number of Note Ons of each pitch class, drums left out, is compared to the Krumhansl-Kessler key profiles
+    /// and the best matching key is returned. It can be fooled by modal or atonal music, and often mistakes a key for its relative or dominant.
+    /// Returns None if there are no notes or all pitch classes are played as often.
+    pub fn estimate_key(&self) -> Option<KeySignature> {
+        let mut histogram = [0f64; 12];
+        for (_, _, event) in self.iter_absolute() {
+            if let Event::NoteOn(n) = event {
+                if event.is_note_on() && n.channel != DRUM_CHANNEL {
+                    histogram[usize::from(n.key % 12)] += 1.0;
+                }
+            }
+        }
+        // Best correlation so far, with the tonic and mode
+        let mut best: Option<(f64, usize, Key)> = None;
+        for (profile, key) in [(MAJOR_PROFILE, Key::Major), (MINOR_PROFILE, Key::Minor)].iter() {
+            for tonic in 0..12 {
+                let rotated: Vec<f64> = (0..12).map(|pc| profile[(pc + 12 - tonic) % 12]).collect();
+                let r = correlation(&histogram, &rotated)?;
+                if best.is_none_or(|(b, _, _)| r > b) {
+                    best = Some((r, tonic, *key));
+                }
+            }
+        }
+        best.map(|(_, tonic, key)| {
+            // Key Signatures are given by their relative major
+            let major = match key {
+                Key::Major => tonic,
+                Key::Minor => (tonic + 3) % 12,
+            };
+            // Position on the circle of fifths, from 5 flats to 6 sharps
+            let fifths = (major * 7 % 12) as i8;
+            KeySignature {
+                flats_sharps: if fifths > 6 { fifths - 12 } else { fifths },
+                key,
+            }
+        })
+    }
+
+    /// Maps the port numbers set by MIDIPort events to the name of the device behind them, when known.
+    /// A DeviceName names the port in effect in its track, or the first port of the track if it comes before any MIDIPort.
+    pub fn ports(&self) -> HashMap<u8, Option<String>> {
+        let mut ports: HashMap<u8, Option<String>> = HashMap::new();
+        for track in &self.tracks {
+            let mut current = None;
+            // Device name found before any port in this track
+            let mut pending = None;
+            for track_event in &track.track_events {
+                match &track_event.event {
+                    Event::MIDIPort(p) => {
+                        current = Some(p.port);
+                        let name = ports.entry(p.port).or_insert(None);
+                        if name.is_none() {
+                            *name = pending.take();
+                        }
+                    }
+                    Event::DeviceName(d) => match current {
+                        Some(port) => {
+                            let name = ports.entry(port).or_insert(None);
+                            if name.is_none() {
+                                *name = Some(d.text.clone());
+                            }
+                        }
+                        None => pending = pending.or_else(|| Some(d.text.clone())),
+                    },
+                    _ => {}
+                }
+            }
+        }
+        ports
+    }
+
+    /// Lists the different keys played on the General MIDI drum channel, i.e. the percussion sounds a kit needs to play this file.
+    pub fn drum_sounds(&self) -> HashSet<u8> {
+        self.tracks
+            .iter()
+            .flat_map(|track| &track.track_events)
+            .filter_map(|t| match &t.event {
+                Event::NoteOn(n) if n.channel == DRUM_CHANNEL && n.velocity > 0 => Some(n.key),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Names of the General MIDI percussion sounds used in this file, sorted by key. Keys without a General MIDI name are left out.
+    pub fn drum_sound_names(&self) -> Vec<&'static str> {
+        let mut keys: Vec<u8> = self.drum_sounds().into_iter().collect();
+        keys.sort_unstable();
+        keys.into_iter().filter_map(drum_name).collect()
+    }
+
+    /// Tells whether this file only plays drums, never plays drums or does both, depending on the channels of its notes.
+    /// A file without any note is considered Melodic.
+    pub fn classify(&self) -> SongClass {
+        let mut drums = false;
+        let mut melody = false;
+        for track in &self.tracks {
+            for track_event in &track.track_events {
+                if let Event::NoteOn(n) = &track_event.event {
+                    if n.velocity == 0 {
+                        continue;
+                    }
+                    if n.channel == DRUM_CHANNEL {
+                        drums = true;
+                    } else {
+                        melody = true;
+                    }
+                }
+            }
+        }
+        match (drums, melody) {
+            (true, false) => SongClass::DrumsOnly,
+            (true, true) => SongClass::Mixed,
+            (false, _) => SongClass::Melodic,
+        }
+    }
+
+    /// Guesses how the text of the Text-like Meta Events is encoded, the spec not telling. This is only a heuristic:
+    /// each text with non-ASCII bytes counts for UTF-8 if it is valid UTF-8, else for Shift-JIS if it only has valid Shift-JIS sequences, else for Latin-1.
+    /// The most common one wins, UTF-8 first then Shift-JIS on ties, and UTF-8 is returned when every text is ASCII.
+    /// Use it to decode `TextMessage::bytes`, as texts are always decoded as UTF-8 on import.
+    pub fn guess_text_encoding(&self) -> TextEncoding {
+        let mut votes = [0usize; 3];
+        for track in &self.tracks {
+            for track_event in &track.track_events {
+                let bytes = match track_event.event.text_message() {
+                    Some(message) => message.bytes(),
+                    None => continue,
+                };
+                if bytes.is_ascii() {
+                    continue;
+                }
+                let encoding = if std::str::from_utf8(bytes).is_ok() {
+                    TextEncoding::Utf8
+                } else if is_shift_jis(bytes) {
+                    TextEncoding::ShiftJis
+                } else {
+                    TextEncoding::Latin1
+                };
+                votes[encoding as usize] += 1;
+            }
+        }
+        if votes[1] > votes[0] && votes[1] >= votes[2] {
+            TextEncoding::ShiftJis
+        } else if votes[2] > votes[0] && votes[2] > votes[1] {
+            TextEncoding::Latin1
+        } else {
+            TextEncoding::Utf8
+        }
+    }
+
+    /// Returns the tempo of the file in Microseconds per Quarter Note if it never changes, or None if it varies.
+    /// A file without any Tempo event plays at the default 120 BPM, which is also the tempo before a first Tempo event placed after tick 0.
+    pub fn static_tempo(&self) -> Option<u32> {
+        let mut tempos: Vec<(u64, u32)> = self
+            .iter_absolute()
+            .filter_map(|(_, tick, event)| match event {
+                Event::Tempo(t) => Some((tick, t.value)),
+                _ => None,
+            })
+            .collect();
+        tempos.sort_by_key(|(tick, _)| *tick);
+        let value = match tempos.first() {
+            Some((0, value)) => *value,
+            _ => Tempo::DEFAULT,
+        };
+        if tempos.iter().all(|(_, v)| *v == value) {
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    /// Counts the measures the song spans up to its last tick, the last one counting even if it is not complete.
+    /// Time Signature changes are followed, each one starting a new measure, and the song is in 4/4 until the first one.
+    /// Returns None if the time division is in SMPTE, as measures can't be found then.
+    pub fn measure_count(&self) -> Option<u32> {
+        let tpqn = match self.header.time_division {
+            TimeScale::TicksPerQuarterNote(t) => u64::from(t),
+            TimeScale::SMPTECompatible(_, _) => return None,
+        };
+        let changes = self.meters(tpqn);
+        let end = self.length_ticks();
+        let mut measures = 0u64;
+        for (i, (start, measure, _)) in changes.iter().enumerate() {
+            let stop = changes
+                .get(i + 1)
+                .map_or(end, |(next, _, _)| *next)
+                .min(end);
+            if stop > *start {
+                measures += (stop - start).div_ceil(*measure);
+            }
+        }
+        Some(measures as u32)
+    }
+
+    /// Lists the Time Signature changes as `(tick, measure length in ticks, beats per measure)`, sorted by tick.
+    /// The song is in 4/4 until the first one.
+    pub(crate) fn meters(&self, tpqn: u64) -> Vec<(u64, u64, u8)> {
+        let mut changes = vec![(0, 4 * tpqn, 4)];
+        for (_, tick, event) in self.iter_absolute() {
+            if let Event::TimeSignature(t) = event {
+                // A whole note is 4 quarter notes
+                let measure = (4 * tpqn * u64::from(t.numerator)) >> t.denominator.min(63);
+                changes.push((tick, measure.max(1), t.numerator.max(1)));
+            }
+        }
+        // Stable sort, so the last change at a tick wins
+        changes.sort_by_key(|(tick, _, _)| *tick);
+        changes
+    }
+}
+
+/// What a MIDI File plays, see `SMF::classify`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SongClass {
+    /// Notes are only played on the General MIDI drum channel
+    DrumsOnly,
+    /// Notes are never played on the General MIDI drum channel
+    Melodic,
+    /// Notes are played on the drum channel and on other channels
+    Mixed,
+}
+
+/// How the text of Text-like Meta Events is encoded, see `SMF::guess_text_encoding`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TextEncoding {
+    /// Unicode, also covering plain ASCII
+    Utf8,
+    /// Japanese, common in files made with Japanese software
+    ShiftJis,
+    /// ISO 8859-1, Western European languages
+    Latin1,
+}
+
+/// Checks that some bytes only hold valid Shift-JIS characters: ASCII, half-width katakana (0xA1 to 0xDF),
+/// or a lead byte (0x81 to 0x9F, 0xE0 to 0xFC) followed by a trail byte (0x40 to 0xFC except 0x7F).
+fn is_shift_jis(bytes: &[u8]) -> bool {
+    let mut iter = bytes.iter();
+    while let Some(byte) = iter.next() {
+        match byte {
+            0x00..=0x7F | 0xA1..=0xDF => {}
+            0x81..=0x9F | 0xE0..=0xFC => match iter.next() {
+                Some(0x40..=0x7E | 0x80..=0xFC) => {}
+                _ => return false,
+            },
+            _ => return false,
+        }
+    }
+    true
+}
+
+/// Krumhansl-Kessler profile of a major key, from the tonic up by semitones
+const MAJOR_PROFILE: [f64; 12] = [
+    6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88,
+];
+
+/// Krumhansl-Kessler profile of a minor key, from the tonic up by semitones
+const MINOR_PROFILE: [f64; 12] = [
+    6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17,
+];
+
+/// Pearson correlation between two series, None if one of them does not vary
+fn correlation(a: &[f64], b: &[f64]) -> Option<f64> {
+    let mean_a = a.iter().sum::<f64>() / a.len() as f64;
+    let mean_b = b.iter().sum::<f64>() / b.len() as f64;
+    let mut covariance = 0.0;
+    let mut variance_a = 0.0;
+    let mut variance_b = 0.0;
+    for (x, y) in a.iter().zip(b) {
+        covariance += (x - mean_a) * (y - mean_b);
+        variance_a += (x - mean_a) * (x - mean_a);
+        variance_b += (y - mean_b) * (y - mean_b);
+    }
+    if variance_a == 0.0 || variance_b == 0.0 {
+        return None;
+    }
+    Some(covariance / (variance_a * variance_b).sqrt())
+}
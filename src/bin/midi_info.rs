@@ -5,7 +5,6 @@ use clap::{App, Arg};
 use standard_midi_file::header::TimeScale;
 use standard_midi_file::track::event::Event;
 use standard_midi_file::SMF;
-use std::cmp::max;
 use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::BufReader;
@@ -37,7 +36,6 @@ pub fn main() {
     }
 
     let mut tempos = HashMap::new();
-    let mut longest_time = 0;
 
     for (i, track) in smf.tracks.iter().enumerate() {
         println!("---------------------------");
@@ -48,6 +46,8 @@ pub fn main() {
         let mut note_fake_on = 0;
         let mut note_off = 0;
         let mut unk_meta = 0;
+        let mut channel_pressures = 0;
+        let mut key_pressures = 0;
         let mut channels = HashSet::new();
         let mut time = 0;
         for track_event in &track.track_events {
@@ -65,23 +65,34 @@ pub fn main() {
                     }
                     channels.insert(n.channel);
                 }
+                Event::ChannelPressure(c) => {
+                    channel_pressures += 1;
+                    channels.insert(c.channel);
+                }
+                Event::PolyphonicKeyPressure(p) => {
+                    key_pressures += 1;
+                    channels.insert(p.channel);
+                }
                 Event::Tempo(t) => {
                     tempos.insert(time, t.value);
                 }
-                Event::SequenceTrackName(s) => println!("Name: {}", s.text),
+                Event::SequenceTrackName(s) => println!("Name: {}", s.clean_text()),
                 Event::UnknownMetaEvent(_) => unk_meta += 1,
                 _ => {}
             }
         }
-        longest_time = max(longest_time, time);
         println!(
             "{} Real Note Ons, {} Fake Note Offs, {} Actual Note Offs",
             note_full_on, note_fake_on, note_off
         );
+        println!(
+            "{} Channel Pressures, {} Polyphonic Key Pressures",
+            channel_pressures, key_pressures
+        );
         println!("Channels: {:?}", channels);
         println!("{} Unknown Meta Events", unk_meta);
     }
     println!("-----------------------------------------");
     println!("Tempos: {:?}", tempos);
-    println!("Longest Time: {}", longest_time);
+    println!("Longest Time: {}", smf.length_ticks());
 }
@@ -0,0 +1,28 @@
+use crate::Result;
+use std::io::{Read, Seek, SeekFrom};
+
+/// The kind of file found by `detect_format`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DetectedFormat {
+    /// A Standard MIDI File, starting with a MThd chunk
+    Smf,
+    /// A RIFF MIDI File, i.e. a Standard MIDI File wrapped in a RIFF container
+    Rmid,
+    /// Anything else
+    Unknown,
+}
+
+/// Guesses what kind of file this is by looking at its first bytes. The reader is moved back to where it was.
+pub fn detect_format<R: Read + Seek>(reader: &mut R) -> Result<DetectedFormat> {
+    let start = reader.stream_position()?;
+    let mut data = Vec::with_capacity(12);
+    reader.take(12).read_to_end(&mut data)?;
+    reader.seek(SeekFrom::Start(start))?;
+    Ok(if data.starts_with(b"MThd") {
+        DetectedFormat::Smf
+    } else if data.len() == 12 && data.starts_with(b"RIFF") && &data[8..] == b"RMID" {
+        DetectedFormat::Rmid
+    } else {
+        DetectedFormat::Unknown
+    })
+}
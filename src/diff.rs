@@ -0,0 +1,120 @@
+use crate::header::{Format, TimeScale};
+use crate::track::event::Event;
+use crate::track::SMFTrack;
+use crate::SMF;
+
+/// A single difference found between two MIDI Files. Events are located by track index and absolute time in ticks.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SMFDifference {
+    /// The Formats in the headers are different
+    Format(Format, Format),
+    /// The headers report a different number of tracks
+    NbTracks(u16, u16),
+    /// The headers use a different time division
+    TimeDivision(TimeScale, TimeScale),
+    /// An event only present in the other file
+    Added {
+        track: usize,
+        tick: u64,
+        event: Event,
+    },
+    /// An event only present in this file
+    Removed {
+        track: usize,
+        tick: u64,
+        event: Event,
+    },
+    /// An event present in both files at the same time, but with different contents
+    Changed {
+        track: usize,
+        tick: u64,
+        old: Event,
+        new: Event,
+    },
+}
+
+impl SMF {
+    /// Lists every difference between this MIDI File and another one.
+    /// Events happening at the same tick in the same track are compared in order of appearance.
+    pub fn diff(&self, other: &SMF) -> Vec<SMFDifference> {
+        let mut differences = Vec::new();
+        if self.header.format != other.header.format {
+            differences.push(SMFDifference::Format(
+                self.header.format,
+                other.header.format,
+            ));
+        }
+        if self.header.nb_tracks != other.header.nb_tracks {
+            differences.push(SMFDifference::NbTracks(
+                self.header.nb_tracks,
+                other.header.nb_tracks,
+            ));
+        }
+        if self.header.time_division != other.header.time_division {
+            differences.push(SMFDifference::TimeDivision(
+                self.header.time_division,
+                other.header.time_division,
+            ));
+        }
+        let empty = SMFTrack {
+            length: 0,
+            track_events: Vec::new(),
+            offsets: Vec::new(),
+        };
+        for index in 0..self.tracks.len().max(other.tracks.len()) {
+            let old = self.tracks.get(index).unwrap_or(&empty);
+            let new = other.tracks.get(index).unwrap_or(&empty);
+            diff_track(index, old, new, &mut differences);
+        }
+        differences
+    }
+}
+
+/// Compares two tracks tick by tick
+fn diff_track(index: usize, old: &SMFTrack, new: &SMFTrack, differences: &mut Vec<SMFDifference>) {
+    let mut old_events = old.iter_absolute().peekable();
+    let mut new_events = new.iter_absolute().peekable();
+    loop {
+        // Find the next tick to look at
+        let tick = match (old_events.peek(), new_events.peek()) {
+            (Some((o, _)), Some((n, _))) => (*o).min(*n),
+            (Some((o, _)), None) => *o,
+            (None, Some((n, _))) => *n,
+            (None, None) => return,
+        };
+        // Gather everything happening at this tick on both sides
+        let mut old_at_tick = Vec::new();
+        while let Some((_, event)) = old_events.next_if(|(t, _)| *t == tick) {
+            old_at_tick.push(event);
+        }
+        let mut new_at_tick = Vec::new();
+        while let Some((_, event)) = new_events.next_if(|(t, _)| *t == tick) {
+            new_at_tick.push(event);
+        }
+        for i in 0..old_at_tick.len().max(new_at_tick.len()) {
+            match (old_at_tick.get(i), new_at_tick.get(i)) {
+                (Some(o), Some(n)) => {
+                    if o != n {
+                        differences.push(SMFDifference::Changed {
+                            track: index,
+                            tick,
+                            old: (*o).clone(),
+                            new: (*n).clone(),
+                        })
+                    }
+                }
+                (Some(o), None) => differences.push(SMFDifference::Removed {
+                    track: index,
+                    tick,
+                    event: (*o).clone(),
+                }),
+                (None, Some(n)) => differences.push(SMFDifference::Added {
+                    track: index,
+                    tick,
+                    event: (*n).clone(),
+                }),
+                (None, None) => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,311 @@
+use crate::error::SMFError;
+use crate::header::{Format, SMFHeader, TimeScale};
+use crate::track::event::{EndOfTrack, Event, MIDIChannelPrefix, NoteChange};
+use crate::track::SMFTrack;
+use crate::vlv::calc_vlv_length;
+use crate::Result;
+use crate::SMF;
+use std::convert::TryFrom;
+
+impl SMF {
+    /// Builds a single track containing the events of the given tracks, which must exist.
+    /// Events at the same tick are kept in track order.
+    pub(crate) fn merged_track(&self, indices: &[usize]) -> Result<SMFTrack> {
+        let mut events: Vec<(u64, Event)> = Vec::new();
+        for i in indices {
+            events.extend(self.tracks[*i].iter_absolute().map(|(t, e)| (t, e.clone())));
+        }
+        // Stable sort, and the EndOfTracks left in the middle are removed when normalizing
+        events.sort_by_key(|(tick, _)| *tick);
+        SMFTrack::from_absolute(events)
+    }
+
+    /// Merges the given tracks into the one with the lowest index, removing the others.
+    /// Events at the same tick are kept in track order.
+    pub fn merge_tracks(&mut self, indices: &[usize]) -> Result<()> {
+        let mut indices = indices.to_vec();
+        indices.sort_unstable();
+        indices.dedup();
+        if let Some(i) = indices.iter().find(|i| **i >= self.tracks.len()) {
+            return Err(SMFError::TrackIndexOutOfRange(*i));
+        }
+        if indices.len() < 2 {
+            return Ok(());
+        }
+        self.tracks[indices[0]] = self.merged_track(&indices)?;
+        for i in indices[1..].iter().rev() {
+            self.tracks.remove(*i);
+        }
+        self.header.nb_tracks = self.tracks.len() as u16;
+        Ok(())
+    }
+
+    /// Changes the number of ticks per quarter note, scaling every Delta-Time so the music stays the same.
+    /// Times are scaled from the start of each track to avoid accumulating rounding errors. Only works with Ticks per Quarter Note.
+    pub fn resample_division(&mut self, new_ppqn: u16) -> Result<()> {
+        let old_ppqn = match self.header.time_division {
+            TimeScale::TicksPerQuarterNote(t) => t,
+            TimeScale::SMPTECompatible(_, _) => return Err(SMFError::NotTicksPerQuarterNote),
+        };
+        if old_ppqn == 0 || new_ppqn == 0 {
+            return Err(SMFError::InvalidTicksPerQuarterNote(old_ppqn.min(new_ppqn)));
+        }
+        let (old, new) = (u64::from(old_ppqn), u64::from(new_ppqn));
+        // Compute everything first, so nothing is changed on error
+        let mut all_ticks = Vec::with_capacity(self.tracks.len());
+        for track in &self.tracks {
+            let mut ticks = Vec::with_capacity(track.track_events.len());
+            let mut previous = 0;
+            for (tick, _) in track.iter_absolute() {
+                let scaled = (tick * new + old / 2) / old;
+                calc_vlv_length(u32::try_from(scaled - previous).unwrap_or(u32::MAX))?;
+                ticks.push(scaled);
+                previous = scaled;
+            }
+            all_ticks.push(ticks);
+        }
+        for (track, ticks) in self.tracks.iter_mut().zip(all_ticks) {
+            track.set_absolute_ticks(&ticks);
+            track.length = track.computed_length()?;
+        }
+        self.header.time_division = TimeScale::TicksPerQuarterNote(new_ppqn);
+        Ok(())
+    }
+
+    /// Merges every track into a new SingleTrack (Format 0) MIDI File. Events at the same tick are kept in track order.
+    /// With `channel_prefixes`, a MIDIChannelPrefix is inserted each time the events switch to another source track,
+    /// so Meta and SysEx Events stay attributed to the channel of their track. The channel used is the one of the first MIDI Channel Event in the track,
+    /// and tracks without any MIDI Channel Event get no prefix.
+    pub fn to_single_track(&self, channel_prefixes: bool) -> Result<SMF> {
+        let mut sources: Vec<(u64, usize, Event)> = Vec::new();
+        for (i, track) in self.tracks.iter().enumerate() {
+            sources.extend(track.iter_absolute().map(|(t, e)| (t, i, e.clone())));
+        }
+        sources.sort_by_key(|(tick, _, _)| *tick);
+        let prefixes: Vec<Option<u8>> = self
+            .tracks
+            .iter()
+            .map(|track| {
+                track
+                    .track_events
+                    .iter()
+                    .find_map(|t| t.event.status_byte())
+                    .map(|status| status & 0x0F)
+            })
+            .collect();
+        let mut events = Vec::with_capacity(sources.len());
+        let mut current_track = None;
+        for (tick, track, event) in sources {
+            if let Event::EndOfTrack(_) = event {
+                // Removed when normalizing, so it does not start a run
+                events.push((tick, event));
+                continue;
+            }
+            if channel_prefixes && current_track != Some(track) {
+                if let Some(channel) = prefixes[track] {
+                    events.push((
+                        tick,
+                        Event::MIDIChannelPrefix(MIDIChannelPrefix { channel }),
+                    ));
+                }
+                current_track = Some(track);
+            }
+            events.push((tick, event));
+        }
+        Ok(SMF {
+            header: SMFHeader {
+                length: 6,
+                format: Format::SingleTrack,
+                nb_tracks: 1,
+                time_division: self.header.time_division,
+                extra: Vec::new(),
+            },
+            tracks: vec![SMFTrack::from_absolute(events)?],
+            trailing_chunks: self.trailing_chunks.clone(),
+            warnings: Vec::new(),
+        })
+    }
+
+    /// Extracts the events in `[start_tick, end_tick)` of every track into a new MIDI File, moved so that `start_tick` becomes tick 0.
+    /// The Tempo, Time Signature and Programs in effect at `start_tick` in each track are inserted at tick 0, so the slice sounds the same.
+    /// Each track gets a new EndOfTrack, at `end_tick` or at the end of the original track if it comes first.
+    pub fn slice(&self, start_tick: u64, end_tick: u64) -> Result<SMF> {
+        if start_tick > end_tick {
+            return Err(SMFError::InvalidTickRange(start_tick, end_tick));
+        }
+        let mut tracks = Vec::with_capacity(self.tracks.len());
+        for track in &self.tracks {
+            let mut tempo = None;
+            let mut time_signature = None;
+            let mut programs: Vec<Option<Event>> = vec![None; 16];
+            let mut events = Vec::new();
+            let mut track_end = 0;
+            for (tick, event) in track.iter_absolute() {
+                track_end = tick;
+                if tick < start_tick {
+                    match event {
+                        Event::Tempo(_) => tempo = Some(event.clone()),
+                        Event::TimeSignature(_) => time_signature = Some(event.clone()),
+                        Event::ProgramChange(p) => {
+                            programs[usize::from(p.channel & 0x0F)] = Some(event.clone())
+                        }
+                        _ => {}
+                    }
+                } else if tick < end_tick {
+                    if let Event::EndOfTrack(_) = event {
+                        continue;
+                    }
+                    events.push((tick - start_tick, event.clone()));
+                }
+            }
+            let mut carried: Vec<(u64, Event)> = tempo
+                .into_iter()
+                .chain(time_signature)
+                .chain(programs.iter().flatten().cloned())
+                .map(|event| (0, event))
+                .collect();
+            carried.append(&mut events);
+            let end = track_end.clamp(start_tick, end_tick) - start_tick;
+            let last = carried.last().map(|(tick, _)| *tick).unwrap_or(0);
+            carried.push((end.max(last), Event::EndOfTrack(EndOfTrack {})));
+            tracks.push(SMFTrack::from_absolute(carried)?);
+        }
+        Ok(SMF {
+            header: self.header.clone(),
+            tracks,
+            trailing_chunks: self.trailing_chunks.clone(),
+            warnings: Vec::new(),
+        })
+    }
+
+    /// Removes the empty tracks, i.e. those containing nothing but an EndOfTrack, and updates the header.
+    /// At least one track is always kept, and a SingleTrack file with several tracks left gets them merged into one.
+    pub fn compact(&mut self) -> Result<()> {
+        let first = self.tracks.first().cloned();
+        self.tracks.retain(|track| {
+            track
+                .track_events
+                .iter()
+                .any(|t| !matches!(t.event, Event::EndOfTrack(_)))
+        });
+        if self.tracks.is_empty() {
+            self.tracks.extend(first);
+        }
+        if self.header.format == Format::SingleTrack && self.tracks.len() > 1 {
+            let indices: Vec<usize> = (0..self.tracks.len()).collect();
+            self.tracks = vec![self.merged_track(&indices)?];
+        }
+        self.header.nb_tracks = self.tracks.len() as u16;
+        Ok(())
+    }
+
+    /// Calls a closure on every track along with its index, allowing to modify it, then normalizes every track so the file can be exported.
+    pub fn map_tracks<F: FnMut(usize, &mut SMFTrack)>(&mut self, mut f: F) -> Result<()> {
+        for (index, track) in self.tracks.iter_mut().enumerate() {
+            f(index, track);
+            track.normalize()?;
+        }
+        Ok(())
+    }
+
+    /// Replaces the value of every Tempo event, in microseconds per quarter note, by what a closure returns for it, e.g. to slow down gradually.
+    /// Tempos are visited in track order then time order, and the results are clamped to what a Tempo can hold (1 to 2^24-1).
+    pub fn map_tempos<F: FnMut(u32) -> u32>(&mut self, mut f: F) {
+        for track in &mut self.tracks {
+            track.for_each_event_mut(|event| {
+                if let Event::Tempo(tempo) = event {
+                    tempo.value = f(tempo.value).clamp(1, 0xFF_FFFF);
+                }
+            });
+        }
+    }
+
+    /// Adds a track clicking on every beat, e.g. for practice: `accent_note` on the first beat of each measure and `note` on the others.
+    /// Beats follow the Time Signature changes, the song being in 4/4 until the first one, up to the end of the song.
+    /// A Format 0 file becomes Format 1. Only works with Ticks per Quarter Note.
+    /// Fails with InvalidChannel if `channel` is above 15, or InvalidDataByte if a note is above 127.
+    pub fn add_click_track(&mut self, channel: u8, note: u8, accent_note: u8) -> Result<()> {
+        if channel > 15 {
+            return Err(SMFError::InvalidChannel(channel));
+        }
+        if let Some(n) = [note, accent_note].iter().find(|n| **n > 127) {
+            return Err(SMFError::InvalidDataByte(*n));
+        }
+        let tpqn = match self.header.time_division {
+            TimeScale::TicksPerQuarterNote(t) => u64::from(t),
+            TimeScale::SMPTECompatible(_, _) => return Err(SMFError::NotTicksPerQuarterNote),
+        };
+        let meters = self.meters(tpqn);
+        let end = self.length_ticks();
+        let mut events = Vec::new();
+        for (i, (start, measure, beats)) in meters.iter().enumerate() {
+            let stop = meters.get(i + 1).map_or(end, |(next, _, _)| *next).min(end);
+            let beats = u64::from(*beats);
+            let mut measure_start = *start;
+            while measure_start < stop {
+                for beat in 0..beats {
+                    let tick = measure_start + measure * beat / beats;
+                    if tick >= stop {
+                        break;
+                    }
+                    let (key, velocity) = if beat == 0 {
+                        (accent_note, 127)
+                    } else {
+                        (note, 100)
+                    };
+                    // Sound for half a beat
+                    let next_beat = measure_start + measure * (beat + 1) / beats;
+                    let release = tick + ((next_beat - tick) / 2).max(1);
+                    events.push((
+                        tick,
+                        Event::NoteOn(NoteChange {
+                            channel,
+                            key,
+                            velocity,
+                        }),
+                    ));
+                    events.push((
+                        release,
+                        Event::NoteOff(NoteChange {
+                            channel,
+                            key,
+                            velocity: 64,
+                        }),
+                    ));
+                }
+                measure_start += measure;
+            }
+        }
+        // At the same tick, clicks are stopped before new ones start
+        events.sort_by_key(|(tick, event)| (*tick, !event.is_note_off()));
+        self.tracks.push(SMFTrack::from_absolute(events)?);
+        self.header.nb_tracks = self.tracks.len() as u16;
+        if self.header.format == Format::SingleTrack {
+            self.header.format = Format::MultipleTrack;
+        }
+        Ok(())
+    }
+
+    /// Removes the silence before the first note: every track is moved back so the earliest Note On of the file starts at tick 0.
+    /// Events placed before that note, e.g. Tempo or names, are kept in order at tick 0. Nothing changes if there are no notes.
+    pub fn trim_leading_silence(&mut self) -> Result<()> {
+        let first = match self
+            .iter_absolute()
+            .filter(|(_, _, event)| event.is_note_on())
+            .map(|(_, tick, _)| tick)
+            .min()
+        {
+            Some(first) => first,
+            None => return Ok(()),
+        };
+        for track in &mut self.tracks {
+            let ticks: Vec<u64> = track
+                .iter_absolute()
+                .map(|(tick, _)| tick.saturating_sub(first))
+                .collect();
+            track.set_absolute_ticks(&ticks);
+            track.length = track.computed_length()?;
+        }
+        Ok(())
+    }
+}
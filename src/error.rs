@@ -15,16 +15,59 @@ pub enum SMFError {
     UnknownFormat(u16),
     /// Header reports 0 tracks
     NoTracks,
+    /// A SingleTrack (Format 0) MIDI File contains more than one track
+    SingleTrackFormatMismatch(usize),
     /// Reported number of tracks and real amount of tracks do not match
     VecHeaderTracksMismatch(u16, usize),
+    /// The length of a track does not match the size of its events, see `SMFTrack::normalize`
+    TrackLengthMismatch(u32, u32),
+    /// The Delta-Time of the event at this index in its track is too big to fit in a VLV (2^28-1 ticks at most)
+    DeltaTooLarge(usize, u64),
+    /// The operation needs the time division to be in Ticks per Quarter Note, not SMPTE
+    NotTicksPerQuarterNote,
+    /// A number of Ticks per Quarter Note can't be 0
+    InvalidTicksPerQuarterNote(u16),
+    /// A size declared in the file goes beyond a limit set in the ImportOptions
+    LimitExceeded(u64, u64),
+    /// A SysEx or Meta Event declares more data than what is left in its track
+    TruncatedEvent(u32, u64),
+    /// A track index given by the user is not in the MIDI File
+    TrackIndexOutOfRange(usize),
+    /// A range of ticks given by the user starts after its end
+    InvalidTickRange(u64, u64),
+    /// An error raised while reading the Track Event starting at this offset, given when importing with `track_offsets`
+    AtOffset(u64, Box<SMFError>),
     /// Tried to use Running Status on first event
     NoPreviousEvent,
     /// Unknown Event encountered
     UnknownEvent(u8),
+    /// A System Common or System Real-Time message (0xF1 to 0xF6 and 0xF8 to 0xFE) was found in a track, which is not allowed in a MIDI File
+    InvalidInSmf(u8),
     /// Non-standard Meta Event Length
     UnexpectedMetaEventLength(u32),
     /// In a KeySignature Meta Event, if the second byte (major or minor key) is not set to 0 or 1
     KeySignatureUnknownKey(u8),
+    /// A BPM can't be turned into a Tempo, as it would not fit in 24 bits
+    TempoOutOfRange(f64),
+    /// A MIDI channel given by the user is not between 0 and 15
+    InvalidChannel(u8),
+    /// A key, velocity or other MIDI data byte given by the user is not between 0 and 127
+    InvalidDataByte(u8),
+    /// A SMPTE Offset can't be negative or go beyond 24 hours
+    SMPTEOffsetOutOfRange,
+    /// A timecode is not written as "HH:MM:SS:FF.ff"
+    InvalidTimecode(String),
+}
+
+impl SMFError {
+    /// Returns the kind of the underlying IO error, if this is one. Useful to tell a truncated file (UnexpectedEof) from a real IO failure.
+    pub fn io_kind(&self) -> Option<std::io::ErrorKind> {
+        match self {
+            SMFError::IO(ref e) => Some(e.kind()),
+            SMFError::AtOffset(_, ref e) => e.io_kind(),
+            _ => None,
+        }
+    }
 }
 
 impl std::fmt::Display for SMFError {
@@ -38,11 +81,27 @@ impl std::fmt::Display for SMFError {
             }
             SMFError::UnknownFormat(ref e) => write!(f, "Found unknown format in MThd: {}", e),
             SMFError::NoTracks => write!(f, "MThd chunk reports 0 tracks"),
+            SMFError::SingleTrackFormatMismatch(ref e) => write!(f, "A SingleTrack MIDI File must contain exactly one track, but it has {}. Use the MultipleTrack Format instead", e),
             SMFError::VecHeaderTracksMismatch(ref e, ref g) => write!(f, "Amount of tracks reported in header and number of tracks in Vec do not match: Header {}, Vec: {}", e, g),
+            SMFError::TrackLengthMismatch(ref e, ref g) => write!(f, "Track length does not match the size of its events, normalize it first: Length {}, Events: {}", e, g),
+            SMFError::DeltaTooLarge(ref e, ref g) => write!(f, "Delta-Time too big to be written, it must be less than 2^28 ticks: Event {}, Delta-Time: {}", e, g),
+            SMFError::NotTicksPerQuarterNote => write!(f, "This operation requires a time division in Ticks per Quarter Note"),
+            SMFError::InvalidTicksPerQuarterNote(ref e) => write!(f, "Invalid number of Ticks per Quarter Note: {}", e),
+            SMFError::LimitExceeded(ref e, ref g) => write!(f, "A size declared in the file goes beyond the configured limit: Size {}, Limit: {}", e, g),
+            SMFError::TruncatedEvent(ref e, ref g) => write!(f, "An event declares more data than what is left in its track: Declared {}, Remaining: {}", e, g),
+            SMFError::TrackIndexOutOfRange(ref e) => write!(f, "There is no track at index {}", e),
+            SMFError::InvalidTickRange(ref e, ref g) => write!(f, "Range of ticks starts after its end: Start {}, End {}", e, g),
+            SMFError::AtOffset(ref e, ref g) => write!(f, "Error in the Track Event at offset {}: {}", e, g),
             SMFError::NoPreviousEvent => write!(f, "Event is a Running Status, but no previous event"),
             SMFError::UnknownEvent(ref e) => write!(f, "Encountered an Unknown Event while processing a track. Event Code Byte: {}", e),
+            SMFError::InvalidInSmf(ref e) => write!(f, "Encountered a System Common or System Real-Time message, which is not allowed in a MIDI File. Status Byte: {}", e),
             SMFError::UnexpectedMetaEventLength(ref e) => write!(f, "A Meta Event with a defined length used a non-standard size. Length: {}", e),
             SMFError::KeySignatureUnknownKey(ref e) => write!(f, "The specified key in a Key Signature Meta Event was not 0 or 1. Value: {}", e),
+            SMFError::TempoOutOfRange(ref e) => write!(f, "A Tempo can't hold this number of Beats per Minute: {}", e),
+            SMFError::InvalidChannel(ref e) => write!(f, "A MIDI channel must be between 0 and 15: {}", e),
+            SMFError::InvalidDataByte(ref e) => write!(f, "A MIDI data byte must be between 0 and 127: {}", e),
+            SMFError::SMPTEOffsetOutOfRange => write!(f, "SMPTE Offset is negative or not less than 24 hours"),
+            SMFError::InvalidTimecode(ref e) => write!(f, "Timecode is not written as HH:MM:SS:FF.ff: {}", e),
         }
     }
 }
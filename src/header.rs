@@ -1,10 +1,11 @@
 use crate::error::SMFError;
 use crate::Result;
 use ez_io::{MagicNumberCheck, ReadE, WriteE};
+use std::convert::TryFrom;
 use std::io::{Read, Seek, SeekFrom, Write};
 
 /// Contains the information found in a standard 6-byte MThd Header of a MIDI File.
-#[derive(Copy, Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct SMFHeader {
     /// Header Length
     pub length: u32,
@@ -14,12 +15,19 @@ pub struct SMFHeader {
     pub nb_tracks: u16,
     /// Provides information on what the delta times represent
     pub time_division: TimeScale,
+    /// Bytes found after the standard 6 bytes when the header is longer, kept so they can be written back
+    pub extra: Vec<u8>,
 }
 
 impl SMFHeader {
     /// Reads a MThd from a file.
-    pub fn import<R: Read + Seek>(reader: &mut R) -> Result<SMFHeader> {
+    pub fn import<R: Read>(reader: &mut R) -> Result<SMFHeader> {
         reader.check_magic_number(&[b'M', b'T', b'h', b'd'])?;
+        SMFHeader::import_after_magic(reader)
+    }
+
+    /// Reads a MThd whose magic number has already been checked
+    pub(crate) fn import_after_magic<R: Read>(reader: &mut R) -> Result<SMFHeader> {
         let length = reader.read_be_to_u32()?;
         if length < 6 {
             return Err(SMFError::UnexpectedMThdLength(length));
@@ -30,37 +38,54 @@ impl SMFHeader {
             return Err(SMFError::NoTracks);
         }
         let time_division = TimeScale::import(reader)?;
-        if length > 6 {
-            // Skip unknown data.
-            reader.seek(SeekFrom::Current(i64::from(length - 6)))?;
+        // Keep unknown data, read through take as the length can't be trusted to allocate
+        let mut extra = Vec::new();
+        let extra_length = u64::from(length - 6);
+        reader.take(extra_length).read_to_end(&mut extra)?;
+        if (extra.len() as u64) < extra_length {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
         }
         Ok(SMFHeader {
             length,
             format,
             nb_tracks,
             time_division,
+            extra,
         })
     }
 
-    /// Exports the MThd as binary data.
+    /// Reads a MThd without moving the reader, which is left where it was so the whole file can still be imported afterwards.
+    /// This is the intended fast path for tools that only need the format, number of tracks and time division.
+    pub fn read_only<R: Read + Seek>(reader: &mut R) -> Result<SMFHeader> {
+        let start = reader.stream_position()?;
+        let header = SMFHeader::import(reader);
+        reader.seek(SeekFrom::Start(start))?;
+        header
+    }
+
+    /// Exports the MThd as binary data. The length written is 6 plus the number of extra bytes, whatever `length` says.
+    /// Nothing is written if the header can't be exported.
     pub fn export<W: Write>(&self, writer: &mut W) -> Result<()> {
-        writer.write_all(&[b'M', b'T', b'h', b'd'])?;
-        if self.length != 6 {
-            return Err(SMFError::UnexpectedMThdLength(self.length));
-        }
-        writer.write_be_to_u32(self.length)?;
-        self.format.export(writer)?;
         if self.nb_tracks == 0 {
             return Err(SMFError::NoTracks);
         }
-        writer.write_be_to_u16(self.nb_tracks)?;
-        self.time_division.export(writer)?;
+        let length = u32::try_from(6 + self.extra.len())
+            .map_err(|_| SMFError::UnexpectedMThdLength(u32::MAX))?;
+        // Build the whole chunk first, so that an error leaves the writer untouched
+        let mut data = Vec::with_capacity(8 + length as usize);
+        data.write_all(b"MThd")?;
+        data.write_be_to_u32(length)?;
+        self.format.export(&mut data)?;
+        data.write_be_to_u16(self.nb_tracks)?;
+        self.time_division.export(&mut data)?;
+        data.write_all(&self.extra)?;
+        writer.write_all(&data)?;
         Ok(())
     }
 }
 
 /// The Format field in a MIDI File Header.
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Format {
     /// Single Track in the MIDI File.
     SingleTrack,
@@ -82,23 +107,38 @@ impl Format {
 
     /// Reads the u16 format field in the header
     pub fn import<R: Read>(reader: &mut R) -> Result<Format> {
-        Ok(match reader.read_be_to_u16()? {
+        Format::try_from(reader.read_be_to_u16()?)
+    }
+
+    /// Writes the u16 Format field in the header
+    pub fn export<W: Write>(self, writer: &mut W) -> Result<()> {
+        writer.write_be_to_u16(self.get_value())?;
+        Ok(())
+    }
+}
+
+impl TryFrom<u16> for Format {
+    type Error = SMFError;
+
+    /// Gives the Format for its number in the header, failing with UnknownFormat for anything but 0, 1 and 2.
+    fn try_from(value: u16) -> Result<Format> {
+        Ok(match value {
             0 => Format::SingleTrack,
             1 => Format::MultipleTrack,
             2 => Format::MultipleSong,
             x => return Err(SMFError::UnknownFormat(x)),
         })
     }
+}
 
-    /// Writes the u16 Format field in the header
-    pub fn export<W: Write>(self, writer: &mut W) -> Result<()> {
-        writer.write_be_to_u16(self.get_value())?;
-        Ok(())
+impl From<Format> for u16 {
+    fn from(format: Format) -> u16 {
+        format.get_value()
     }
 }
 
 /// The possible time scales a MIDI File can use.
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum TimeScale {
     TicksPerQuarterNote(u16),
     SMPTECompatible(i8, u8),
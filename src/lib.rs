@@ -5,38 +5,233 @@ extern crate ez_io;
 /// The Result used throughout the crate
 type Result<T> = std::result::Result<T, error::SMFError>;
 
+/// Gathering information on MIDI Files
+pub mod analysis;
+/// Recognizing MIDI Files
+pub mod detect;
+/// Comparing MIDI Files
+pub mod diff;
+/// Editing MIDI Files
+mod edit;
 /// Errors used throughout this crate
 pub mod error;
+/// General MIDI names
+pub mod gm;
 /// SMF Header
 pub mod header;
+/// Options for importing and exporting
+pub mod options;
+/// Simpler view of MIDI Files
+pub mod song;
+/// Helpers for counting the bytes going through streams, and reading from streams that cannot Seek
+mod stream;
 /// SMF Track
 pub mod track;
+/// Checking MIDI Files for problems
+pub mod validate;
 /// Stuff for Reading/Creating VLVs
 pub mod vlv;
 
 use error::SMFError;
-use header::SMFHeader;
-use std::io::{Read, Seek, Write};
+use ez_io::WriteE;
+use header::{Format, SMFHeader};
+use options::{ExportOptions, ImportOptions, ImportWarning};
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use stream::{check_magic, read_available, read_chunk_id, CountingReader, CountingWriter};
+use track::event::{Event, EventKind, ManufacturerId, Verbosity};
 use track::SMFTrack;
 
 /// The Primary type for this crate. This is the primary way to Import and Export MIDI Files and manipulate them.
+/// It only holds plain data, so it is Send and Sync and can be shared with other threads, e.g. an audio thread.
 #[derive(Clone)]
 pub struct SMF {
     /// The MThd header of a MIDI File. Contains useful info for reading the rest.
     pub header: SMFHeader,
     /// The MTrk tracks of a MIDI file. This is where the actual "music" is held.
     pub tracks: Vec<SMFTrack>,
+    /// Chunks of an unknown type found after the tracks, as their 4 byte id and their data, e.g. Yamaha XF "XFIH" and "XFKM" chunks.
+    /// They are written back after the tracks on export.
+    pub trailing_chunks: Vec<([u8; 4], Vec<u8>)>,
+    /// Unusual things found while importing this file, see ImportOptions
+    pub warnings: Vec<ImportWarning>,
 }
 
 impl SMF {
-    /// Imports an entire MIDI File.
-    pub fn import<R: Read + Seek>(reader: &mut R) -> Result<SMF> {
-        let header = SMFHeader::import(reader)?;
+    /// Imports an entire MIDI File. Only Read is needed, so this also works on pipes and other streams.
+    pub fn import<R: Read>(reader: &mut R) -> Result<SMF> {
+        SMF::import_with_options(reader, &ImportOptions::default())
+    }
+
+    /// Imports an entire MIDI File with some options, e.g. limits on sizes when the file can't be trusted.
+    /// Offsets recorded with `track_offsets` are from the start of the file.
+    /// Chunks found after the tracks are kept in `trailing_chunks`, until the end of the stream or the start of another MIDI File.
+    pub fn import_with_options<R: Read>(reader: &mut R, options: &ImportOptions) -> Result<SMF> {
+        Ok(SMF::import_until_next(reader, options, |_| {})?.0)
+    }
+
+    /// Imports an entire MIDI File, and returns the id of the next MThd chunk if one follows it, as it has already been read.
+    /// `on_track` is called with the reader after each track is read, extra tracks included.
+    fn import_until_next<R: Read, F: FnMut(&R)>(
+        reader: &mut R,
+        options: &ImportOptions,
+        mut on_track: F,
+    ) -> Result<(SMF, Option<[u8; 4]>)> {
+        let mut warnings = Vec::new();
+        if let Some(found) = check_magic(reader, b"MThd", options.lenient_magic)? {
+            warnings.push(ImportWarning::LenientMagic { offset: 0, found });
+        }
+        let mut header = SMFHeader::import_after_magic(reader)?;
         let mut tracks = Vec::with_capacity(header.nb_tracks as usize);
+        // Start of the next track data in the file, after its magic number and length
+        let mut data_start = 8 + u64::from(header.length) + 8;
         for _ in 0..header.nb_tracks {
-            tracks.push(SMFTrack::import(reader)?);
+            let track = import_track_at(reader, options, data_start, &mut warnings)?;
+            data_start += u64::from(track.length) + 8;
+            tracks.push(track);
+            on_track(reader);
+        }
+        let (trailing_chunks, next) = read_trailing_chunks(
+            reader,
+            options,
+            &mut tracks,
+            data_start - 8,
+            &mut warnings,
+            &mut on_track,
+        )?;
+        if tracks.len() > header.nb_tracks as usize {
+            header.nb_tracks = tracks.len() as u16;
+        }
+        Ok((
+            SMF {
+                header,
+                tracks,
+                trailing_chunks,
+                warnings,
+            },
+            next,
+        ))
+    }
+
+    /// Lists the songs in this MIDI File, each one being a single track that can be played on its own.
+    /// For a MultipleSong file, every track is a song. For a SingleTrack file, the only track is the song.
+    /// For a MultipleTrack file, there is a single song made of all the tracks merged together.
+    pub fn songs(&self) -> Result<Vec<Cow<'_, SMFTrack>>> {
+        Ok(match self.header.format {
+            Format::SingleTrack | Format::MultipleSong => {
+                self.tracks.iter().map(Cow::Borrowed).collect()
+            }
+            Format::MultipleTrack => {
+                let indices: Vec<usize> = (0..self.tracks.len()).collect();
+                vec![Cow::Owned(self.merged_track(&indices)?)]
+            }
+        })
+    }
+
+    /// Writes every track to its own single track MIDI File in a directory, named with a prefix and the index of the track.
+    /// For a MultipleTrack file, the Tempo events of the first (conductor) track are copied into every other track.
+    /// Returns the paths of the created files.
+    pub fn export_tracks_separately<P: AsRef<Path>>(
+        &self,
+        dir: P,
+        prefix: &str,
+    ) -> Result<Vec<PathBuf>> {
+        let mut tempos = Vec::new();
+        if let (Format::MultipleTrack, Some(conductor)) = (self.header.format, self.tracks.first())
+        {
+            for (tick, event) in conductor.iter_absolute() {
+                if let Event::Tempo(_) = event {
+                    tempos.push((tick, event.clone()));
+                }
+            }
+        }
+        let mut paths = Vec::with_capacity(self.tracks.len());
+        for (i, track) in self.tracks.iter().enumerate() {
+            let track = if i == 0 || tempos.is_empty() {
+                let mut track = track.clone();
+                track.normalize()?;
+                track
+            } else {
+                // Tempos first, so they apply to the other events at the same tick
+                let mut events = tempos.clone();
+                events.extend(track.iter_absolute().map(|(t, e)| (t, e.clone())));
+                events.sort_by_key(|(tick, _)| *tick);
+                SMFTrack::from_absolute(events)?
+            };
+            let smf = SMF {
+                header: SMFHeader {
+                    length: 6,
+                    format: Format::SingleTrack,
+                    nb_tracks: 1,
+                    time_division: self.header.time_division,
+                    extra: Vec::new(),
+                },
+                tracks: vec![track],
+                trailing_chunks: Vec::new(),
+                warnings: Vec::new(),
+            };
+            let path = dir.as_ref().join(format!("{}{}.mid", prefix, i));
+            smf.export(&mut BufWriter::new(File::create(&path)?))?;
+            paths.push(path);
+        }
+        Ok(paths)
+    }
+
+    /// Returns the time in ticks of the last event of the longest track, EndOfTrack included.
+    pub fn length_ticks(&self) -> u64 {
+        self.tracks
+            .iter()
+            .map(|track| track.duration_ticks())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Lists the manufacturers of every device targeted by a SysEx message in this file.
+    pub fn sysex_manufacturers(&self) -> HashSet<ManufacturerId> {
+        let mut manufacturers = HashSet::new();
+        for track in &self.tracks {
+            for track_event in &track.track_events {
+                let manufacturer = match &track_event.event {
+                    Event::SystemExclusiveF0(s) => s.manufacturer(false),
+                    Event::SystemExclusiveF7(s) => s.manufacturer(true),
+                    _ => None,
+                };
+                if let Some(m) = manufacturer {
+                    manufacturers.insert(m);
+                }
+            }
+        }
+        manufacturers
+    }
+
+    /// Reads only the header of a MIDI File on disk, without reading any of its tracks.
+    pub fn peek_header<P: AsRef<Path>>(path: P) -> Result<SMFHeader> {
+        // No buffering, so nothing past the header is read from the file
+        SMFHeader::import(&mut File::open(path)?)
+    }
+
+    /// Imports every MIDI File stored back-to-back in a stream, until the end of it is reached.
+    pub fn import_all<R: Read>(reader: &mut R) -> Result<Vec<SMF>> {
+        let mut smfs = Vec::new();
+        // Read the first bytes ourselves to tell a clean end of stream apart from a truncated file
+        let (mut magic, mut read_bytes) = read_chunk_id(reader)?;
+        while read_bytes > 0 {
+            let (smf, next) = SMF::import_until_next(
+                &mut (&magic[..read_bytes]).chain(&mut *reader),
+                &ImportOptions::default(),
+                |_| {},
+            )?;
+            smfs.push(smf);
+            // The end of the stream was reached if no other MThd followed
+            match next {
+                Some(next) => magic = next,
+                None => read_bytes = 0,
+            }
         }
-        Ok(SMF { header, tracks })
+        Ok(smfs)
     }
 
     /// Exports an entire MIDI File.
@@ -51,6 +246,326 @@ impl SMF {
         for track in &self.tracks {
             track.export(writer)?;
         }
+        for (id, data) in &self.trailing_chunks {
+            export_chunk(writer, id, data)?;
+        }
+        Ok(())
+    }
+
+    /// Exports an entire MIDI File with some options, e.g. snapping events to a grid for reproducible output.
+    /// The lengths of the tracks are computed again when events are moved.
+    pub fn export_with_options<W: Write>(
+        &self,
+        writer: &mut W,
+        options: &ExportOptions,
+    ) -> Result<()> {
+        let grid = match options.snap_grid {
+            Some(grid) if grid > 0 => u64::from(grid),
+            _ => return self.export(writer),
+        };
+        let mut smf = self.clone();
+        for track in &mut smf.tracks {
+            // Rounding to the nearest multiple never puts an event before the previous one
+            let ticks: Vec<u64> = track
+                .iter_absolute()
+                .map(|(tick, _)| (tick + grid / 2) / grid * grid)
+                .collect();
+            track.set_absolute_ticks(&ticks);
+            track.length = track.computed_length()?;
+        }
+        smf.export(writer)
+    }
+
+    /// Iterates over the events of every track, one track after the other, along with the index of their track and their absolute time in ticks.
+    pub fn iter_absolute(&self) -> impl Iterator<Item = (usize, u64, &Event)> {
+        self.tracks.iter().enumerate().flat_map(|(i, track)| {
+            track
+                .iter_absolute()
+                .map(move |(tick, event)| (i, tick, event))
+        })
+    }
+
+    /// Iterates over the events of a given kind in every track, along with the index of their track and their absolute time in ticks.
+    pub fn events_of_kind(&self, kind: EventKind) -> impl Iterator<Item = (usize, u64, &Event)> {
+        self.iter_absolute()
+            .filter(move |(_, _, event)| event.kind() == kind)
+    }
+
+    /// Checks that the Format of this file allows its number of tracks: a SingleTrack file must contain exactly one track.
+    /// Many players reject files breaking this rule.
+    pub fn check_format_consistency(&self) -> Result<()> {
+        if self.header.format == Format::SingleTrack && self.tracks.len() != 1 {
+            return Err(SMFError::SingleTrackFormatMismatch(self.tracks.len()));
+        }
         Ok(())
     }
+
+    /// Imports an entire MIDI File, calling `progress` with the number of bytes read so far and `total_len` after each track.
+    /// This allows showing a progress bar while reading big files. Like `import`, only Read is needed.
+    pub fn import_with_progress<R: Read, F: FnMut(u64, u64)>(
+        reader: &mut R,
+        total_len: u64,
+        mut progress: F,
+    ) -> Result<SMF> {
+        let mut counter = CountingReader::new(reader);
+        let (smf, _) =
+            SMF::import_until_next(&mut counter, &ImportOptions::default(), |counter| {
+                progress(counter.count, total_len)
+            })?;
+        Ok(smf)
+    }
+
+    /// Exports an entire MIDI File, returning the number of bytes written. Useful to know the size of a file sent over the network without buffering it.
+    pub fn export_with_size<W: Write>(&self, writer: &mut W) -> Result<u64> {
+        let mut counter = CountingWriter::new(writer);
+        self.export(&mut counter)?;
+        Ok(counter.count)
+    }
+
+    /// Computes the size in bytes of the entire MIDI File once exported, without writing it, e.g. to allocate a buffer.
+    /// Track lengths come from `computed_length` rather than the stored ones, like on export.
+    pub fn computed_size(&self) -> Result<u64> {
+        let mut size = 14 + self.header.extra.len() as u64;
+        for track in &self.tracks {
+            size += 8 + u64::from(track.computed_length()?);
+        }
+        for (_, data) in &self.trailing_chunks {
+            size += 8 + data.len() as u64;
+        }
+        Ok(size)
+    }
+
+    /// Imports an entire MIDI File held in memory, e.g. embedded in another file format.
+    pub fn from_bytes(data: &[u8]) -> Result<SMF> {
+        SMF::import(&mut &data[..])
+    }
+
+    /// Exports an entire MIDI File to a new buffer.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        self.export(&mut data)?;
+        Ok(data)
+    }
+
+    /// Imports a gzipped MIDI File (".mid.gz"), decompressing it on the fly.
+    #[cfg(feature = "gzip")]
+    pub fn import_gz<R: Read>(reader: &mut R) -> Result<SMF> {
+        SMF::import(&mut flate2::read::GzDecoder::new(reader))
+    }
+
+    /// Exports a gzipped MIDI File.
+    #[cfg(feature = "gzip")]
+    pub fn export_gz<W: Write>(&self, writer: &mut W) -> Result<()> {
+        let mut encoder = flate2::write::GzEncoder::new(writer, flate2::Compression::default());
+        self.export(&mut encoder)?;
+        encoder.finish()?;
+        Ok(())
+    }
+
+    /// Imports a MIDI File on disk. With the `gzip` feature, gzipped files are recognized by their first bytes and decompressed.
+    pub fn read_from_path<P: AsRef<Path>>(path: P) -> Result<SMF> {
+        let mut reader = BufReader::new(File::open(path)?);
+        #[cfg(feature = "gzip")]
+        {
+            use std::io::BufRead;
+            if reader.fill_buf()?.starts_with(&[0x1F, 0x8B]) {
+                return SMF::import_gz(&mut reader);
+            }
+        }
+        SMF::import(&mut reader)
+    }
+
+    /// Writes a human readable dump of this MIDI File: the header, then every track with one event per line, indented,
+    /// along with its absolute time and Delta-Time in ticks. Meant for snapshots that can be compared with a text diff.
+    pub fn to_text<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writeln!(
+            writer,
+            "Format: {:?}, Tracks: {}, Time Division: {:?}",
+            self.header.format, self.header.nb_tracks, self.header.time_division
+        )?;
+        for (i, track) in self.tracks.iter().enumerate() {
+            writeln!(writer, "Track {}", i)?;
+            let mut tick = 0u64;
+            for track_event in &track.track_events {
+                tick += u64::from(track_event.delta_time.value);
+                writeln!(
+                    writer,
+                    "    {} (+{}): {}",
+                    tick,
+                    track_event.delta_time.value,
+                    track_event.event.describe(Verbosity::Normal)
+                )?;
+            }
+        }
+        for (id, data) in &self.trailing_chunks {
+            writeln!(
+                writer,
+                "Chunk {}: {} bytes",
+                String::from_utf8_lossy(id),
+                data.len()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// A chunk as its id and its data
+type Chunk = ([u8; 4], Vec<u8>);
+
+/// Reads a MTrk chunk whose data starts at `data_start` in the file, moving the offsets it records to be from the start of the file.
+/// Chunk ids accepted thanks to `lenient_magic` are added to the warnings.
+fn import_track_at<R: Read>(
+    reader: &mut R,
+    options: &ImportOptions,
+    data_start: u64,
+    warnings: &mut Vec<ImportWarning>,
+) -> Result<SMFTrack> {
+    if let Some(found) = check_magic(reader, b"MTrk", options.lenient_magic)? {
+        warnings.push(ImportWarning::LenientMagic {
+            offset: data_start - 8,
+            found,
+        });
+    }
+    let mut track = match SMFTrack::import_after_magic(reader, options) {
+        Ok(track) => track,
+        Err(SMFError::AtOffset(offset, e)) => {
+            return Err(SMFError::AtOffset(data_start + offset, e))
+        }
+        Err(e) => return Err(e),
+    };
+    for offset in &mut track.offsets {
+        *offset += data_start;
+    }
+    Ok(track)
+}
+
+/// Reads the chunks following the tracks, the first one starting at `offset` in the file, until the end of the stream or until a MThd chunk starts another MIDI File.
+/// In the latter case, the id of that chunk is returned as it has already been read.
+/// Data too short to be a chunk at the end of the stream is ignored with a warning, keeping the chunks read before it.
+/// MTrk chunks are added to the tracks with `read_extra_tracks`, calling `on_track` after each one, and with `ignore_trailing_data` reading stops without error at the first chunk that can't be parsed.
+fn read_trailing_chunks<R: Read, F: FnMut(&R)>(
+    reader: &mut R,
+    options: &ImportOptions,
+    tracks: &mut Vec<SMFTrack>,
+    offset: u64,
+    warnings: &mut Vec<ImportWarning>,
+    on_track: &mut F,
+) -> Result<(Vec<Chunk>, Option<[u8; 4]>)> {
+    let mut chunks = Vec::new();
+    match read_chunks(
+        reader,
+        options,
+        tracks,
+        offset,
+        warnings,
+        &mut chunks,
+        on_track,
+    ) {
+        Ok(next) => Ok((chunks, next)),
+        Err(e) if options.ignore_trailing_data && is_unreadable_data(&e) => Ok((chunks, None)),
+        Err(e) => Err(e),
+    }
+}
+
+/// Tells if an error comes from data that can't be parsed, rather than from a limit in the ImportOptions or a failing reader.
+fn is_unreadable_data(error: &SMFError) -> bool {
+    match error {
+        SMFError::IO(e) => e.kind() == ErrorKind::UnexpectedEof,
+        SMFError::AtOffset(_, e) => is_unreadable_data(e),
+        SMFError::LimitExceeded(_, _) => false,
+        _ => true,
+    }
+}
+
+/// Reads chunks into `chunks` and MTrk chunks into `tracks` if asked, see `read_trailing_chunks`.
+fn read_chunks<R: Read, F: FnMut(&R)>(
+    reader: &mut R,
+    options: &ImportOptions,
+    tracks: &mut Vec<SMFTrack>,
+    mut offset: u64,
+    warnings: &mut Vec<ImportWarning>,
+    chunks: &mut Vec<Chunk>,
+    on_track: &mut F,
+) -> Result<Option<[u8; 4]>> {
+    loop {
+        let mut id = [0u8; 4];
+        match read_available(reader, &mut id)? {
+            0 => return Ok(None),
+            4 if id == *b"MThd" => return Ok(Some(id)),
+            4 if id == *b"MTrk" && options.read_extra_tracks => {
+                let track = import_track_at(
+                    &mut (&id[..]).chain(&mut *reader),
+                    options,
+                    offset + 8,
+                    warnings,
+                )?;
+                offset += u64::from(track.length) + 8;
+                tracks.push(track);
+                on_track(reader);
+                continue;
+            }
+            4 => {}
+            _ => {
+                warnings.push(ImportWarning::TruncatedChunk { offset });
+                return Ok(None);
+            }
+        }
+        let mut length = [0u8; 4];
+        if read_available(reader, &mut length)? < length.len() {
+            warnings.push(ImportWarning::TruncatedChunk { offset });
+            return Ok(None);
+        }
+        let length = u32::from_be_bytes(length);
+        if let Some(max) = options.max_track_bytes {
+            if length > max {
+                return Err(SMFError::LimitExceeded(u64::from(length), u64::from(max)));
+            }
+        }
+        let mut data = Vec::new();
+        reader.take(u64::from(length)).read_to_end(&mut data)?;
+        if data.len() < length as usize {
+            warnings.push(ImportWarning::TruncatedChunk { offset });
+            return Ok(None);
+        }
+        offset += u64::from(length) + 8;
+        chunks.push((id, data));
+    }
+}
+
+/// Writes a chunk with its id, length and data.
+fn export_chunk<W: Write>(writer: &mut W, id: &[u8; 4], data: &[u8]) -> Result<()> {
+    writer.write_all(id)?;
+    writer.write_be_to_u32(data.len() as u32)?;
+    writer.write_all(data)?;
+    Ok(())
+}
+
+/// Only gives a summary of the file, as printing every event would be unreadable.
+/// Each track is shown as its name, its number of events and its length.
+impl std::fmt::Debug for SMF {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let tracks: Vec<(Option<&str>, usize, u32)> = self
+            .tracks
+            .iter()
+            .map(|track| {
+                let name = track.track_events.iter().find_map(|t| match &t.event {
+                    Event::SequenceTrackName(s) => Some(s.text.as_str()),
+                    _ => None,
+                });
+                (name, track.track_events.len(), track.length)
+            })
+            .collect();
+        f.debug_struct("SMF")
+            .field("format", &self.header.format)
+            .field("nb_tracks", &self.header.nb_tracks)
+            .field("time_division", &self.header.time_division)
+            .field("tracks", &tracks)
+            .finish()
+    }
+}
+
+/// Fails to compile if SMF ever stops being Send and Sync.
+fn _assert_send_sync() {
+    fn assert<T: Send + Sync>() {}
+    assert::<SMF>();
 }
@@ -0,0 +1,38 @@
+/// Options for reading a MIDI File. The default reads any file with no limit, like `import` does.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ImportOptions {
+    /// Largest size in bytes a track can declare. Useful to reject untrusted files that are unreasonably big.
+    pub max_track_bytes: Option<u32>,
+    /// Largest amount of data bytes a single SysEx or Meta Event can declare, as this data is allocated before being read.
+    pub max_event_data: Option<usize>,
+    /// Records where each Track Event starts in the file into `SMFTrack::offsets`, and gives this offset in errors raised while reading an event.
+    pub track_offsets: bool,
+    /// Reads every MTrk chunk found after the number of tracks declared in the header as a track too, updating that number.
+    pub read_extra_tracks: bool,
+    /// Stops reading without error at the first chunk after the tracks that can't be parsed, e.g. a broken extra track.
+    /// Limits such as `max_track_bytes` and errors from the reader itself are still reported.
+    /// Data too short to be a chunk at the end of the file is always ignored, see ImportWarning::TruncatedChunk.
+    pub ignore_trailing_data: bool,
+    /// Accepts chunk ids written with another case, e.g. "mthd", adding an ImportWarning for each of them.
+    pub lenient_magic: bool,
+    /// Discards the data of SysEx, Text-like and other variable length Meta Events instead of reading it, for tools only interested in notes.
+    /// These events are kept with an empty payload and a length of 0, so exporting the file loses their contents.
+    /// Nothing is allocated for the skipped data, so `max_event_data` does not apply to it.
+    pub skip_payloads: bool,
+}
+
+/// Something unusual found while importing a MIDI File, that did not prevent reading it.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ImportWarning {
+    /// A chunk id at this offset in the file was accepted despite its case thanks to `lenient_magic`
+    LenientMagic { offset: u64, found: [u8; 4] },
+    /// Data at this offset at the end of the file is too short to be a chunk, e.g. padding or a truncated download, and was ignored
+    TruncatedChunk { offset: u64 },
+}
+
+/// Options for writing a MIDI File. The default writes the file as is, like `export` does.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ExportOptions {
+    /// Moves every event to the nearest multiple of this many ticks, so files with tiny timing differences give the same bytes. Events keep their order.
+    pub snap_grid: Option<u32>,
+}
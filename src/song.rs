@@ -0,0 +1,93 @@
+use crate::header::{Format, SMFHeader, TimeScale};
+use crate::track::event::Event;
+use crate::track::SMFTrack;
+use crate::Result;
+use crate::SMF;
+
+/// A simpler view of a MIDI File, where events are placed at absolute times in ticks.
+/// There are no Delta-Times, VLVs or lengths to keep up to date, this is all done when converting back to a SMF.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AbstractSong {
+    /// Format of the MIDI File
+    pub format: Format,
+    /// What the ticks represent
+    pub time_division: TimeScale,
+    /// Events of each track with their absolute time in ticks
+    pub tracks: Vec<Vec<(u64, Event)>>,
+}
+
+/// An event of a MIDI File placed in time, as given by `SMF::timed_event_stream`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimedEvent {
+    /// Absolute time of the event in ticks
+    pub tick: u64,
+    /// Index of the track the event comes from
+    pub track: usize,
+    /// The event itself
+    pub event: Event,
+}
+
+impl SMF {
+    /// Converts this MIDI File to its simpler form
+    pub fn to_abstract(&self) -> AbstractSong {
+        AbstractSong {
+            format: self.header.format,
+            time_division: self.header.time_division,
+            tracks: self
+                .tracks
+                .iter()
+                .map(|track| track.iter_absolute().map(|(t, e)| (t, e.clone())).collect())
+                .collect(),
+        }
+    }
+
+    /// Builds a MIDI File from its simpler form. Events of each track are sorted by time, keeping the order of those at the same tick,
+    /// and every track is made to end with an EndOfTrack.
+    pub fn from_abstract(song: AbstractSong) -> Result<SMF> {
+        let mut tracks = Vec::with_capacity(song.tracks.len());
+        for mut events in song.tracks {
+            events.sort_by_key(|(tick, _)| *tick);
+            tracks.push(SMFTrack::from_absolute(events)?);
+        }
+        Ok(SMF {
+            header: SMFHeader {
+                length: 6,
+                format: song.format,
+                nb_tracks: tracks.len() as u16,
+                time_division: song.time_division,
+                extra: Vec::new(),
+            },
+            tracks,
+            trailing_chunks: Vec::new(),
+            warnings: Vec::new(),
+        })
+    }
+
+    /// Iterates over the events of every track merged together and sorted by time, which a playback clock can consume directly.
+    /// Events at the same tick come in track order. Tracks are merged as the iterator goes, without gathering every event first.
+    pub fn timed_event_stream(&self) -> impl Iterator<Item = TimedEvent> + '_ {
+        let mut tracks: Vec<_> = self
+            .tracks
+            .iter()
+            .map(|track| track.iter_absolute().peekable())
+            .collect();
+        std::iter::from_fn(move || {
+            // Track with the earliest next event, the first one winning ties
+            let mut next: Option<(usize, u64)> = None;
+            for (index, events) in tracks.iter_mut().enumerate() {
+                if let Some((tick, _)) = events.peek() {
+                    if next.is_none_or(|(_, t)| *tick < t) {
+                        next = Some((index, *tick));
+                    }
+                }
+            }
+            let (track, _) = next?;
+            let (tick, event) = tracks[track].next()?;
+            Some(TimedEvent {
+                tick,
+                track,
+                event: event.clone(),
+            })
+        })
+    }
+}
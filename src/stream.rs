@@ -0,0 +1,140 @@
+use crate::error::SMFError;
+use crate::Result;
+use ez_io::MagicNumberCheck;
+use std::io::{copy, sink, ErrorKind, Read, Write};
+
+/// Wraps a Reader and counts how many bytes went through it, so that no Seek is needed to know where we are at.
+pub(crate) struct CountingReader<R: Read> {
+    /// The wrapped Reader
+    inner: R,
+    /// Number of bytes read so far
+    pub count: u64,
+}
+
+impl<R: Read> CountingReader<R> {
+    /// Wraps a Reader, starting the count at 0
+    pub fn new(inner: R) -> CountingReader<R> {
+        CountingReader { inner, count: 0 }
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.count += read as u64;
+        Ok(read)
+    }
+}
+
+/// Wraps a Writer and counts how many bytes went through it.
+pub(crate) struct CountingWriter<W: Write> {
+    /// The wrapped Writer
+    inner: W,
+    /// Number of bytes written so far
+    pub count: u64,
+}
+
+impl<W: Write> CountingWriter<W> {
+    /// Wraps a Writer, starting the count at 0
+    pub fn new(inner: W) -> CountingWriter<W> {
+        CountingWriter { inner, count: 0 }
+    }
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Discards a given amount of bytes from a Reader. This replaces a forward Seek for streams that cannot Seek.
+pub(crate) fn skip<R: Read>(reader: &mut R, amount: u64) -> Result<()> {
+    let skipped = copy(&mut reader.take(amount), &mut sink())?;
+    if skipped < amount {
+        return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+    }
+    Ok(())
+}
+
+/// Discards what is left of a Meta Event after its known fields have been read.
+/// Fails if the length declared by the event is smaller than what was read, as this means the event was malformed.
+pub(crate) fn skip_remaining<R: Read>(
+    reader: &mut R,
+    declared_len: u32,
+    consumed: u32,
+) -> Result<()> {
+    if declared_len < consumed {
+        return Err(SMFError::UnexpectedMetaEventLength(declared_len));
+    }
+    skip(reader, u64::from(declared_len - consumed))
+}
+
+/// Checks the 4 bytes identifying a chunk. When lenient, a case variation (e.g. "mthd") is accepted and the bytes found are returned.
+pub(crate) fn check_magic<R: Read>(
+    reader: &mut R,
+    magic: &[u8; 4],
+    lenient: bool,
+) -> Result<Option<[u8; 4]>> {
+    if !lenient {
+        reader.check_magic_number(magic)?;
+        return Ok(None);
+    }
+    let mut found = [0u8; 4];
+    reader.read_exact(&mut found)?;
+    if found == *magic {
+        Ok(None)
+    } else if found.eq_ignore_ascii_case(magic) {
+        Ok(Some(found))
+    } else {
+        // Let the strict check report the error
+        (&found[..]).check_magic_number(magic)?;
+        Ok(None)
+    }
+}
+
+/// Reads the 4 bytes identifying a chunk, returning how many could be read before the end of the stream.
+/// This tells a clean end of stream (0 bytes) apart from a truncated chunk.
+pub(crate) fn read_chunk_id<R: Read>(reader: &mut R) -> Result<([u8; 4], usize)> {
+    let mut id = [0u8; 4];
+    let read_bytes = read_available(reader, &mut id)?;
+    Ok((id, read_bytes))
+}
+
+/// Fills a buffer as much as possible, returning how many bytes could be read before the end of the stream.
+pub(crate) fn read_available<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<usize> {
+    let mut read_bytes = 0;
+    while read_bytes < buf.len() {
+        match reader.read(&mut buf[read_bytes..]) {
+            Ok(0) => break,
+            Ok(n) => read_bytes += n,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(read_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skip_remaining_under_length() {
+        let data = [0xAA, 0xBB];
+        let mut reader = &data[..];
+        match skip_remaining(&mut reader, 2, 3) {
+            Err(SMFError::UnexpectedMetaEventLength(2)) => {}
+            r => panic!("Expected UnexpectedMetaEventLength, got {:?}", r),
+        }
+        // Nothing was consumed
+        assert_eq!(reader.len(), 2);
+        skip_remaining(&mut reader, 3, 1).unwrap();
+        assert!(reader.is_empty());
+    }
+}
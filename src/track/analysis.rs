@@ -0,0 +1,227 @@
+use super::event::Event;
+use super::SMFTrack;
+use std::collections::{HashMap, VecDeque};
+
+impl SMFTrack {
+    /// Pairs every Note On with the Note Off ending it, as indices in the track events.
+    /// When a key is played again before being released, the first Note Off ends the first note.
+    /// Notes never released are left out.
+    pub(crate) fn note_pairs(&self) -> Vec<(usize, usize)> {
+        let mut pairs = Vec::new();
+        // Indices of the Note Ons waiting for their Note Off, by channel and key
+        let mut sounding: HashMap<(u8, u8), VecDeque<usize>> = HashMap::new();
+        for (i, track_event) in self.track_events.iter().enumerate() {
+            match &track_event.event {
+                Event::NoteOn(n) if track_event.event.is_note_on() => {
+                    sounding.entry((n.channel, n.key)).or_default().push_back(i)
+                }
+                Event::NoteOn(n) | Event::NoteOff(n) => {
+                    if let Some(start) = sounding
+                        .get_mut(&(n.channel, n.key))
+                        .and_then(|s| s.pop_front())
+                    {
+                        pairs.push((start, i));
+                    }
+                }
+                _ => {}
+            }
+        }
+        pairs.sort_unstable();
+        pairs
+    }
+
+    /// Finds the notes that would hang on playback, as `(start_tick, channel, key)`.
+    /// These are the Note Ons never followed by a matching Note Off before the end of the track,
+    /// and the Note Ons followed by another Note On of the same key and channel before any Note Off.
+    pub fn stuck_notes(&self) -> Vec<(u64, u8, u8)> {
+        let mut stuck = Vec::new();
+        // Start tick of every sounding note, by channel and key
+        let mut sounding = HashMap::new();
+        for (tick, event) in self.iter_absolute() {
+            match event {
+                Event::NoteOn(n) if event.is_note_on() => {
+                    if let Some(start) = sounding.insert((n.channel, n.key), tick) {
+                        stuck.push((start, n.channel, n.key));
+                    }
+                }
+                Event::NoteOn(n) | Event::NoteOff(n) => {
+                    sounding.remove(&(n.channel, n.key));
+                }
+                _ => {}
+            }
+        }
+        stuck.extend(
+            sounding
+                .into_iter()
+                .map(|((channel, key), start)| (start, channel, key)),
+        );
+        stuck.sort_unstable();
+        stuck
+    }
+
+    /// Gives the number of this sequence in a Format 2 file, or of the pattern in a cue message, from the Sequence Number at the start of the track.
+    /// See `SMF::validate` for Sequence Numbers placed elsewhere, which are ignored here.
+    pub fn sequence_number(&self) -> Option<u16> {
+        self.iter_absolute()
+            .take_while(|(tick, event)| {
+                *tick == 0
+                    && event.status_byte().is_none()
+                    && !matches!(
+                        event,
+                        Event::SystemExclusiveF0(_) | Event::SystemExclusiveF7(_)
+                    )
+            })
+            .find_map(|(_, event)| match event {
+                Event::SequenceNumber(s) => Some(s.sequence_number),
+                _ => None,
+            })
+    }
+
+    /// Counts, for each of the 16 channels, the Note Ons minus the Note Offs, a Note On with a velocity of 0 being a Note Off.
+    /// Anything but 0 means some notes are never released, or released more than once. This is quicker than pairing every note.
+    pub fn note_balance(&self) -> [i32; 16] {
+        let mut balance = [0; 16];
+        for track_event in &self.track_events {
+            match &track_event.event {
+                Event::NoteOn(n) if track_event.event.is_note_on() => {
+                    balance[usize::from(n.channel & 0x0F)] += 1
+                }
+                Event::NoteOn(n) | Event::NoteOff(n) => balance[usize::from(n.channel & 0x0F)] -= 1,
+                _ => {}
+            }
+        }
+        balance
+    }
+
+    /// Gives the lowest, highest and average velocity of the notes played in this track, or None if there are none.
+    /// Note Ons with a velocity of 0 are Note Offs and are not counted.
+    pub fn velocity_stats(&self) -> Option<VelocityStats> {
+        let velocities: Vec<u8> = self
+            .track_events
+            .iter()
+            .filter_map(|t| match &t.event {
+                Event::NoteOn(n) if n.velocity > 0 => Some(n.velocity),
+                _ => None,
+            })
+            .collect();
+        Some(VelocityStats {
+            min: *velocities.iter().min()?,
+            max: *velocities.iter().max()?,
+            mean: velocities.iter().map(|v| f64::from(*v)).sum::<f64>() / velocities.len() as f64,
+        })
+    }
+
+    /// Builds a piano roll of this track: for each of the 128 keys, a column every `resolution_ticks` ticks, true when a note of that key sounds during it.
+    /// Notes that are never released are left out. The grid takes 128 bytes per column, so a long track with a fine resolution can take a lot of memory.
+    pub fn piano_roll(&self, resolution_ticks: u32) -> Vec<Vec<bool>> {
+        if resolution_ticks == 0 {
+            return vec![Vec::new(); 128];
+        }
+        let resolution = u64::from(resolution_ticks);
+        let ticks: Vec<u64> = self.iter_absolute().map(|(tick, _)| tick).collect();
+        let end = ticks.last().copied().unwrap_or(0);
+        let columns = end.div_ceil(resolution) as usize;
+        let mut roll = vec![vec![false; columns]; 128];
+        for (on, off) in self.note_pairs() {
+            let key = match &self.track_events[on].event {
+                Event::NoteOn(n) => usize::from(n.key & 0x7F),
+                _ => continue,
+            };
+            let (start, stop) = (ticks[on], ticks[off]);
+            if stop <= start {
+                continue;
+            }
+            for column in (start / resolution)..=((stop - 1) / resolution) {
+                roll[key][column as usize] = true;
+            }
+        }
+        roll
+    }
+
+    /// Counts the notes starting in each window of `window_ticks` ticks, as `(window_start_tick, note_on_count)`.
+    /// Windows go from the start to the end of the track, empty ones included, which shows busy and sparse sections.
+    pub fn note_density(&self, window_ticks: u32) -> Vec<(u64, u32)> {
+        if window_ticks == 0 {
+            return Vec::new();
+        }
+        let window = u64::from(window_ticks);
+        let end = self
+            .iter_absolute()
+            .last()
+            .map(|(tick, _)| tick)
+            .unwrap_or(0);
+        let mut counts = vec![0u32; end.div_ceil(window).max(1) as usize];
+        for (tick, event) in self.iter_absolute() {
+            if event.is_note_on() {
+                let index = (tick / window) as usize;
+                if index >= counts.len() {
+                    counts.resize(index + 1, 0);
+                }
+                counts[index] += 1;
+            }
+        }
+        counts
+            .into_iter()
+            .enumerate()
+            .map(|(i, count)| (i as u64 * window, count))
+            .collect()
+    }
+
+    /// Lists the notes of this track, with a Note Off immediately followed by a Note On of the same key and channel at the same tick merged into a single note.
+    /// This brings back tied notes as exported by notation software. Notes are sorted by start tick, and keep the velocity of their first Note On.
+    pub fn legato_notes(&self) -> Vec<Note> {
+        let ticks: Vec<u64> = self.iter_absolute().map(|(tick, _)| tick).collect();
+        let mut notes: Vec<Note> = Vec::new();
+        // Index in notes of the last note of each channel and key
+        let mut last: HashMap<(u8, u8), usize> = HashMap::new();
+        for (on, off) in self.note_pairs() {
+            let n = match &self.track_events[on].event {
+                Event::NoteOn(n) => n,
+                _ => continue,
+            };
+            let (start, end) = (ticks[on], ticks[off]);
+            if let Some(&i) = last.get(&(n.channel, n.key)) {
+                let previous = &mut notes[i];
+                if previous.end == start {
+                    previous.end = end;
+                    continue;
+                }
+            }
+            last.insert((n.channel, n.key), notes.len());
+            notes.push(Note {
+                channel: n.channel,
+                key: n.key,
+                velocity: n.velocity,
+                start,
+                end,
+            });
+        }
+        notes
+    }
+}
+
+/// Statistics on the velocities of the notes in a track
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct VelocityStats {
+    /// Lowest velocity
+    pub min: u8,
+    /// Highest velocity
+    pub max: u8,
+    /// Average velocity
+    pub mean: f64,
+}
+
+/// A note played in a track, from its Note On to its Note Off
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Note {
+    /// MIDI Channel of the note
+    pub channel: u8,
+    /// Key of the note
+    pub key: u8,
+    /// Velocity of the Note On
+    pub velocity: u8,
+    /// Absolute time of the Note On, in ticks
+    pub start: u64,
+    /// Absolute time of the Note Off, in ticks
+    pub end: u64,
+}
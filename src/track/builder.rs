@@ -0,0 +1,62 @@
+use super::event::{Event, NoteChange};
+use super::SMFTrack;
+use crate::Result;
+
+/// Helps creating a track by placing events in time instead of handling Delta-Times by hand.
+/// A cursor holds the time in ticks where the last event was placed, every new event is placed relative to it.
+#[derive(Clone, Debug, Default)]
+pub struct TrackBuilder {
+    /// Time of the cursor in ticks
+    cursor: u64,
+    /// Every event added so far, with its absolute time in ticks
+    events: Vec<(u64, Event)>,
+}
+
+impl TrackBuilder {
+    /// Starts an empty track
+    pub fn new() -> TrackBuilder {
+        TrackBuilder::default()
+    }
+
+    /// Places an event some ticks after the cursor, and moves the cursor to it.
+    pub fn event(&mut self, delta_time: u32, event: Event) -> &mut TrackBuilder {
+        self.cursor += u64::from(delta_time);
+        self.events.push((self.cursor, event));
+        self
+    }
+
+    /// Places a Note On some ticks after the cursor and the matching Note Off `duration` ticks later.
+    /// The cursor moves to the Note On, so a chord is made using a `start_delta` of 0 and a melody using the duration of the previous note.
+    pub fn note(
+        &mut self,
+        channel: u8,
+        key: u8,
+        velocity: u8,
+        start_delta: u32,
+        duration: u32,
+    ) -> &mut TrackBuilder {
+        self.cursor += u64::from(start_delta);
+        let note = NoteChange {
+            channel,
+            key,
+            velocity,
+        };
+        self.events.push((self.cursor, Event::NoteOn(note)));
+        self.events.push((
+            self.cursor + u64::from(duration),
+            Event::NoteOff(NoteChange {
+                velocity: 64,
+                ..note
+            }),
+        ));
+        self
+    }
+
+    /// Creates the track, with an EndOfTrack right after the last event.
+    pub fn build(&self) -> Result<SMFTrack> {
+        let mut events = self.events.clone();
+        // At the same tick, notes are stopped before new ones start
+        events.sort_by_key(|(tick, event)| (*tick, !event.is_note_off()));
+        SMFTrack::from_absolute(events)
+    }
+}
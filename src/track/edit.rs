@@ -0,0 +1,384 @@
+use super::event::{EndOfTrack, Event, EventKind, NoteChange, ProgramChange, TextMessage};
+use super::{SMFTrack, TrackEvent};
+use crate::error::SMFError;
+use crate::gm::DRUM_CHANNEL;
+use crate::vlv::{MAX_DELTA_TIME, VLV};
+use crate::Result;
+use std::collections::HashSet;
+
+impl SMFTrack {
+    /// Builds a normalized track from events with their absolute time in ticks, which must already be sorted.
+    /// Fails with DeltaTooLarge if two events are too far apart to be written, see `computed_length`.
+    pub(crate) fn from_absolute(events: Vec<(u64, Event)>) -> Result<SMFTrack> {
+        let mut track_events = Vec::with_capacity(events.len() + 1);
+        let mut previous = 0;
+        for (i, (tick, event)) in events.into_iter().enumerate() {
+            let delta_time = tick - previous;
+            if delta_time > u64::from(MAX_DELTA_TIME) {
+                return Err(SMFError::DeltaTooLarge(i, delta_time));
+            }
+            track_events.push(TrackEvent {
+                delta_time: VLV::new(delta_time as u32)?,
+                event,
+            });
+            previous = tick;
+        }
+        let mut track = SMFTrack {
+            length: 0,
+            track_events,
+            offsets: Vec::new(),
+        };
+        track.normalize()?;
+        Ok(track)
+    }
+
+    /// Sets the Delta-Times of every event from absolute times in ticks, which must not be decreasing.
+    pub(crate) fn set_absolute_ticks(&mut self, ticks: &[u64]) {
+        let mut previous = 0;
+        for (track_event, tick) in self.track_events.iter_mut().zip(ticks) {
+            track_event.delta_time.value = (tick - previous) as u32;
+            previous = *tick;
+        }
+    }
+
+    /// Snaps the length of every note to a grid in ticks, by moving its Note Off. Note Ons do not move.
+    /// Notes are at least `min_len` ticks long (and never 0), and are shortened if needed so they end before the same key is played again.
+    pub fn quantize_durations(&mut self, grid: u32, min_len: u32) -> Result<()> {
+        if grid == 0 {
+            return Ok(());
+        }
+        let grid = u64::from(grid);
+        let mut ticks: Vec<u64> = self.iter_absolute().map(|(tick, _)| tick).collect();
+        let pairs = self.note_pairs();
+        for (n, (on, off)) in pairs.iter().enumerate() {
+            let start = ticks[*on];
+            let length = ticks[*off] - start;
+            let snapped = ((length + grid / 2) / grid * grid)
+                .max(u64::from(min_len))
+                .max(1);
+            let mut end = start + snapped;
+            // Do not overlap with the next note on the same key
+            let (channel, key) = note_key(&self.track_events[*on].event);
+            if let Some((next_on, _)) = pairs[n + 1..]
+                .iter()
+                .find(|(o, _)| note_key(&self.track_events[*o].event) == (channel, key))
+            {
+                // Two Note Ons at the same tick still leave a note of 1 tick
+                end = end.min(ticks[*next_on]).max(start + 1);
+            }
+            ticks[*off] = end;
+        }
+        let mut events: Vec<(u64, Event)> = ticks
+            .into_iter()
+            .zip(self.track_events.drain(..).map(|t| t.event))
+            .collect();
+        events.sort_by_key(|(tick, _)| *tick);
+        notes_off_first(&mut events);
+        *self = SMFTrack::from_absolute(events)?;
+        Ok(())
+    }
+
+    /// Moves every Note On by a pseudo-random amount of ticks, up to `max_jitter_ticks` earlier or later, to make playback sound less mechanical.
+    /// The same seed always gives the same result. Notes never move past their neighbouring events, so the order of events is kept:
+    /// a Note On directly followed by its Note Off, or by any other event at the same tick, can only move earlier.
+    /// As Delta-Times change, `length` is outdated until `normalize` is called.
+    pub fn humanize(&mut self, max_jitter_ticks: u32, seed: u64) {
+        let mut rng = SplitMix64(seed);
+        let mut ticks: Vec<u64> = self.iter_absolute().map(|(tick, _)| tick).collect();
+        let range = 2 * u64::from(max_jitter_ticks) + 1;
+        for i in 0..ticks.len() {
+            match &self.track_events[i].event {
+                Event::NoteOn(n) if n.velocity > 0 => {}
+                _ => continue,
+            }
+            let jitter = (rng.next() % range) as i64 - i64::from(max_jitter_ticks);
+            // Stay between the previous event (already moved) and the next one
+            let earliest = if i == 0 { 0 } else { ticks[i - 1] };
+            let latest = match ticks.get(i + 1) {
+                Some(t) => *t,
+                None => ticks[i],
+            };
+            let moved = (ticks[i] as i64 + jitter).max(earliest as i64) as u64;
+            ticks[i] = moved.min(latest);
+        }
+        self.set_absolute_ticks(&ticks);
+    }
+
+    /// Snaps every Note On to a grid of `grid_ticks`, delaying those falling on the off-beats (odd subdivisions) to swing them.
+    /// `swing_ratio` is where an off-beat lands between two on-beats, from 0.5 for straight notes to about 0.66 for triplet swing, and is kept between 0 and 1.
+    /// Other events do not move, and notes never move past their neighbouring events, so the order of events is kept.
+    /// As Delta-Times change, `length` is outdated until `normalize` is called.
+    pub fn swing(&mut self, grid_ticks: u32, swing_ratio: f64) {
+        if grid_ticks == 0 {
+            return;
+        }
+        let grid = u64::from(grid_ticks);
+        let offset = (2.0 * grid as f64 * swing_ratio.clamp(0.0, 1.0)).round() as u64;
+        let mut ticks: Vec<u64> = self.iter_absolute().map(|(tick, _)| tick).collect();
+        for i in 0..ticks.len() {
+            if !self.track_events[i].event.is_note_on() {
+                continue;
+            }
+            let step = (ticks[i] + grid / 2) / grid;
+            let swung = if step % 2 == 1 {
+                (step - 1) * grid + offset
+            } else {
+                step * grid
+            };
+            // Stay between the previous event (already moved) and the next one
+            let earliest = if i == 0 { 0 } else { ticks[i - 1] };
+            let latest = match ticks.get(i + 1) {
+                Some(t) => *t,
+                None => swung,
+            };
+            ticks[i] = swung.clamp(earliest, latest.max(earliest));
+        }
+        self.set_absolute_ticks(&ticks);
+    }
+
+    /// Writes every note ending the same way: as a NoteOff, or as a NoteOn with a velocity of 0.
+    /// NoteOffs created here get the default release velocity of 64, and the release velocity of converted NoteOffs is lost.
+    /// Both take the same size, so the length of the track does not change.
+    pub fn normalize_note_offs(&mut self, use_note_off: bool) {
+        self.for_each_event_mut(|event| {
+            *event = match event {
+                Event::NoteOn(n) if use_note_off && n.velocity == 0 => {
+                    Event::NoteOff(NoteChange { velocity: 64, ..*n })
+                }
+                Event::NoteOff(n) if !use_note_off => {
+                    Event::NoteOn(NoteChange { velocity: 0, ..*n })
+                }
+                _ => return,
+            }
+        });
+    }
+
+    /// Moves every note of this track by a number of semitones, keys going out of range being clamped to 0 or 127.
+    /// Polyphonic Key Pressures follow their notes. Pass `skip_drums` as true in most cases, so the notes on the General MIDI drum channel (9),
+    /// which each play a different sound, are left untouched. When false, every note is transposed.
+    pub fn transpose(&mut self, semitones: i8, skip_drums: bool) {
+        self.for_each_event_mut(|event| {
+            let (channel, key) = match event {
+                Event::NoteOn(n) | Event::NoteOff(n) => (n.channel, &mut n.key),
+                Event::PolyphonicKeyPressure(p) => (p.channel, &mut p.key),
+                _ => return,
+            };
+            if skip_drums && channel == DRUM_CHANNEL {
+                return;
+            }
+            *key = (i16::from(*key) + i16::from(semitones)).clamp(0, 127) as u8;
+        });
+    }
+
+    /// Selects the instrument of a channel from the start of this track: the Program Change at tick 0 on this channel is updated,
+    /// or a new one is added, after the Bank Selects (controllers 0 and 32) already there so they apply to it.
+    pub fn set_program(&mut self, channel: u8, program: u8) -> Result<()> {
+        let mut position = 0;
+        for (i, track_event) in self.track_events.iter_mut().enumerate() {
+            if track_event.delta_time.value != 0 {
+                break;
+            }
+            match &mut track_event.event {
+                Event::ProgramChange(p) if p.channel == channel => {
+                    p.program = program;
+                    return Ok(());
+                }
+                Event::ControllerChange(c)
+                    if c.channel == channel && matches!(c.controller_number, 0 | 32) =>
+                {
+                    position = i + 1
+                }
+                _ => {}
+            }
+        }
+        let program_change = ProgramChange { channel, program };
+        self.length += 1 + program_change.get_length();
+        self.track_events.insert(
+            position,
+            TrackEvent {
+                delta_time: VLV::new(0)?,
+                event: Event::ProgramChange(program_change),
+            },
+        );
+        Ok(())
+    }
+
+    /// Plays this track backwards (retrograde): an event at tick `t` moves to `last_tick - t`, the last tick being the one of the EndOfTrack.
+    /// Every note keeps its length, its Note On and Note Off trading places. Events happening at the same tick are reversed as well.
+    pub fn reverse(&mut self) -> Result<()> {
+        let mut ticks: Vec<u64> = self.iter_absolute().map(|(tick, _)| tick).collect();
+        let last_tick = self.duration_ticks();
+        let pairs = self.note_pairs();
+        for tick in &mut ticks {
+            *tick = last_tick - *tick;
+        }
+        // A note now starts where it used to end
+        for (on, off) in pairs {
+            ticks.swap(on, off);
+        }
+        let mut events: Vec<(u64, Event)> = ticks
+            .into_iter()
+            .zip(self.track_events.drain(..).map(|t| t.event))
+            .filter(|(_, event)| !matches!(event, Event::EndOfTrack(_)))
+            .collect();
+        events.reverse();
+        events.sort_by_key(|(tick, _)| *tick);
+        notes_off_first(&mut events);
+        events.push((last_tick, Event::EndOfTrack(EndOfTrack {})));
+        *self = SMFTrack::from_absolute(events)?;
+        Ok(())
+    }
+
+    /// Merges the Text-like Meta Events of a given kind that directly follow each other at the same tick (Delta-Time of 0) into one, joining their text.
+    /// Useful for files where lyrics are written one character per event. Events of other kinds are left untouched.
+    pub fn coalesce_text(&mut self, kind: EventKind) -> Result<()> {
+        let mut track_events: Vec<TrackEvent> = Vec::with_capacity(self.track_events.len());
+        for track_event in self.track_events.drain(..) {
+            if track_event.delta_time.value == 0 && track_event.event.kind() == kind {
+                let previous = track_events
+                    .last_mut()
+                    .filter(|p| p.event.kind() == kind)
+                    .and_then(|p| p.event.text_message_mut());
+                if let (Some(previous), Some(text)) = (previous, track_event.event.text_message()) {
+                    let mut data = previous.bytes().to_vec();
+                    data.extend_from_slice(text.bytes());
+                    *previous = TextMessage::from_bytes(data)?;
+                    continue;
+                }
+            }
+            track_events.push(track_event);
+        }
+        self.track_events = track_events;
+        self.length = self.computed_length()?;
+        Ok(())
+    }
+
+    /// Removes every MIDI Channel Event on a channel, along with the MIDIChannelPrefixes pointing to it, e.g. to mute an instrument.
+    /// The other events stay at the same time, see `map_events`.
+    pub fn remove_channel(&mut self, channel: u8) -> Result<()> {
+        self.map_events(|delta_time, event| {
+            let on_channel = match &event {
+                Event::MIDIChannelPrefix(p) => p.channel == channel,
+                e => e.channel() == Some(channel),
+            };
+            if on_channel {
+                None
+            } else {
+                Some((delta_time, event))
+            }
+        })
+    }
+
+    /// Removes the Note Ons repeating a key already started at the same tick on the same channel, along with the Note Offs ending them, keeping the first one.
+    /// A key played again at a later tick without having been released is left alone, see `stuck_notes` to find those.
+    pub fn dedup_simultaneous_notes(&mut self) -> Result<()> {
+        let ticks: Vec<u64> = self.iter_absolute().map(|(tick, _)| tick).collect();
+        let pairs = self.note_pairs();
+        let mut dropped = HashSet::new();
+        // Note Ons already seen at the current tick, by channel and key
+        let mut started = HashSet::new();
+        let mut current_tick = None;
+        for (i, track_event) in self.track_events.iter().enumerate() {
+            if !track_event.event.is_note_on() {
+                continue;
+            }
+            if current_tick != Some(ticks[i]) {
+                current_tick = Some(ticks[i]);
+                started.clear();
+            }
+            if !started.insert(note_key(&track_event.event)) {
+                dropped.insert(i);
+                if let Some((_, off)) = pairs.iter().find(|(on, _)| *on == i) {
+                    dropped.insert(*off);
+                }
+            }
+        }
+        let mut index = 0;
+        self.map_events(|delta_time, event| {
+            index += 1;
+            if dropped.contains(&(index - 1)) {
+                None
+            } else {
+                Some((delta_time, event))
+            }
+        })
+    }
+
+    /// Rebuilds this track by passing every event along with its Delta-Time to a closure, which returns the new pair or None to drop the event.
+    /// The Delta-Time of a dropped event is added to the next kept one, so the following events stay at the same time.
+    /// The track is normalized afterwards. On error, the track is left unchanged.
+    pub fn map_events<F: FnMut(u32, Event) -> Option<(u32, Event)>>(
+        &mut self,
+        mut f: F,
+    ) -> Result<()> {
+        let mut track_events = Vec::with_capacity(self.track_events.len());
+        // Delta-Time of the dropped events, to carry over to the next one
+        let mut carry = 0u32;
+        for track_event in &self.track_events {
+            let delta_time = track_event.delta_time.value;
+            match f(delta_time, track_event.event.clone()) {
+                Some((delta_time, event)) => {
+                    track_events.push(TrackEvent {
+                        delta_time: VLV::new(delta_time.saturating_add(carry))?,
+                        event,
+                    });
+                    carry = 0;
+                }
+                None => carry = carry.saturating_add(delta_time),
+            }
+        }
+        let mut track = SMFTrack {
+            length: 0,
+            track_events,
+            offsets: Vec::new(),
+        };
+        track.normalize()?;
+        self.track_events = track.track_events;
+        self.length = track.length;
+        Ok(())
+    }
+}
+
+/// Small SplitMix64 pseudo-random generator, so results can be reproduced from a seed without any dependency.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+/// Channel and key of a note event
+/// At each tick of events sorted by time, moves Note Offs before Note Ons so notes are stopped before new ones start.
+/// Other events keep their place, and note events of the same kind keep their order.
+fn notes_off_first(events: &mut [(u64, Event)]) {
+    let mut start = 0;
+    while start < events.len() {
+        let tick = events[start].0;
+        let end = start
+            + events[start..]
+                .iter()
+                .take_while(|(t, _)| *t == tick)
+                .count();
+        let slots: Vec<usize> = (start..end)
+            .filter(|&i| matches!(events[i].1, Event::NoteOn(_) | Event::NoteOff(_)))
+            .collect();
+        let mut notes: Vec<Event> = slots.iter().map(|&i| events[i].1.clone()).collect();
+        notes.sort_by_key(|event| !event.is_note_off());
+        for (i, event) in slots.into_iter().zip(notes) {
+            events[i].1 = event;
+        }
+        start = end;
+    }
+}
+
+fn note_key(event: &Event) -> (u8, u8) {
+    match event {
+        Event::NoteOn(n) | Event::NoteOff(n) => (n.channel, n.key),
+        _ => (0xFF, 0xFF),
+    }
+}
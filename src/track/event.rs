@@ -1,12 +1,16 @@
 use crate::error::SMFError;
-use crate::vlv::VLV;
+use crate::options::ImportOptions;
+use crate::stream::{skip, skip_remaining};
+use crate::vlv::{calc_vlv_length, VLV};
 use crate::Result;
 use ez_io::{ReadE, WriteE};
-use std::io::{Read, Seek, SeekFrom, Write};
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::io::{Read, Write};
 
 /// An Event in a TrackEvent inside a Track of a MIDI File.
 /// This particular implementation puts all the MIDI, SysEx and Meta Events in a single place for convenience.
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Event {
     /// Stops the sound of a currently active note
     NoteOff(NoteChange),
@@ -37,6 +41,8 @@ pub enum Event {
     CuePoint(TextMessage),
     ProgramName(TextMessage),
     DeviceName(TextMessage),
+    /// Text using one of the Meta Event types 0x0A to 0x0F, reserved for text but without a defined meaning. Some files use 0x0A for channel names.
+    OtherText(OtherText),
     MIDIChannelPrefix(MIDIChannelPrefix),
     MIDIPort(MIDIPort),
     /// Marks the end of a track, obligatory.
@@ -51,6 +57,40 @@ pub enum Event {
     UnknownMetaEvent(GenericMetaEvent),
 }
 
+/// The kind of an Event, without its contents. Useful to look for a type of event.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    NoteOff,
+    NoteOn,
+    PolyphonicKeyPressure,
+    ControllerChange,
+    ProgramChange,
+    ChannelPressure,
+    PitchBend,
+    SystemExclusiveF0,
+    SystemExclusiveF7,
+    SequenceNumber,
+    Text,
+    Copyright,
+    SequenceTrackName,
+    InstrumentName,
+    Lyric,
+    Marker,
+    CuePoint,
+    ProgramName,
+    DeviceName,
+    OtherText,
+    MIDIChannelPrefix,
+    MIDIPort,
+    EndOfTrack,
+    Tempo,
+    SMPTEOffset,
+    TimeSignature,
+    KeySignature,
+    SequencerSpecificEvent,
+    UnknownMetaEvent,
+}
+
 impl Event {
     /// Returns the length in bytes of this event, everything taken into account.
     pub fn get_length(&self) -> Result<u32> {
@@ -74,6 +114,7 @@ impl Event {
             Event::CuePoint(c) => c.get_length()?,
             Event::ProgramName(p) => p.get_length()?,
             Event::DeviceName(d) => d.get_length()?,
+            Event::OtherText(o) => o.message.get_length()?,
             Event::MIDIChannelPrefix(m) => m.get_length(),
             Event::MIDIPort(m) => m.get_length(),
             Event::EndOfTrack(e) => e.get_length(),
@@ -86,10 +127,135 @@ impl Event {
         })
     }
 
+    /// Returns the kind of this event
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Event::NoteOff(_) => EventKind::NoteOff,
+            Event::NoteOn(_) => EventKind::NoteOn,
+            Event::PolyphonicKeyPressure(_) => EventKind::PolyphonicKeyPressure,
+            Event::ControllerChange(_) => EventKind::ControllerChange,
+            Event::ProgramChange(_) => EventKind::ProgramChange,
+            Event::ChannelPressure(_) => EventKind::ChannelPressure,
+            Event::PitchBend(_) => EventKind::PitchBend,
+            Event::SystemExclusiveF0(_) => EventKind::SystemExclusiveF0,
+            Event::SystemExclusiveF7(_) => EventKind::SystemExclusiveF7,
+            Event::SequenceNumber(_) => EventKind::SequenceNumber,
+            Event::Text(_) => EventKind::Text,
+            Event::Copyright(_) => EventKind::Copyright,
+            Event::SequenceTrackName(_) => EventKind::SequenceTrackName,
+            Event::InstrumentName(_) => EventKind::InstrumentName,
+            Event::Lyric(_) => EventKind::Lyric,
+            Event::Marker(_) => EventKind::Marker,
+            Event::CuePoint(_) => EventKind::CuePoint,
+            Event::ProgramName(_) => EventKind::ProgramName,
+            Event::DeviceName(_) => EventKind::DeviceName,
+            Event::OtherText(_) => EventKind::OtherText,
+            Event::MIDIChannelPrefix(_) => EventKind::MIDIChannelPrefix,
+            Event::MIDIPort(_) => EventKind::MIDIPort,
+            Event::EndOfTrack(_) => EventKind::EndOfTrack,
+            Event::Tempo(_) => EventKind::Tempo,
+            Event::SMPTEOffset(_) => EventKind::SMPTEOffset,
+            Event::TimeSignature(_) => EventKind::TimeSignature,
+            Event::KeySignature(_) => EventKind::KeySignature,
+            Event::SequencerSpecificEvent(_) => EventKind::SequencerSpecificEvent,
+            Event::UnknownMetaEvent(_) => EventKind::UnknownMetaEvent,
+        }
+    }
+
+    /// Returns the message of a Text-like Meta Event (Text, Copyright, Lyric, Marker...), or None for other events.
+    pub fn text_message(&self) -> Option<&TextMessage> {
+        match self {
+            Event::Text(t)
+            | Event::Copyright(t)
+            | Event::SequenceTrackName(t)
+            | Event::InstrumentName(t)
+            | Event::Lyric(t)
+            | Event::Marker(t)
+            | Event::CuePoint(t)
+            | Event::ProgramName(t)
+            | Event::DeviceName(t) => Some(t),
+            Event::OtherText(o) => Some(&o.message),
+            _ => None,
+        }
+    }
+
+    /// Mutable version of `text_message`
+    pub fn text_message_mut(&mut self) -> Option<&mut TextMessage> {
+        match self {
+            Event::Text(t)
+            | Event::Copyright(t)
+            | Event::SequenceTrackName(t)
+            | Event::InstrumentName(t)
+            | Event::Lyric(t)
+            | Event::Marker(t)
+            | Event::CuePoint(t)
+            | Event::ProgramName(t)
+            | Event::DeviceName(t) => Some(t),
+            Event::OtherText(o) => Some(&mut o.message),
+            _ => None,
+        }
+    }
+
+    /// True for a Note On with a velocity above 0, i.e. a note actually starting
+    pub fn is_note_on(&self) -> bool {
+        match self {
+            Event::NoteOn(n) => n.velocity > 0,
+            _ => false,
+        }
+    }
+
+    /// True for a Note Off, or a Note On with a velocity of 0 which means the same thing
+    pub fn is_note_off(&self) -> bool {
+        match self {
+            Event::NoteOff(_) => true,
+            Event::NoteOn(n) => n.velocity == 0,
+            _ => false,
+        }
+    }
+
+    /// Returns the status byte (event type and channel) of a MIDI Channel Event, or None for SysEx and Meta Events.
+    pub fn status_byte(&self) -> Option<u8> {
+        Some(match self {
+            Event::NoteOff(n) => 0x80 | n.channel,
+            Event::NoteOn(n) => 0x90 | n.channel,
+            Event::PolyphonicKeyPressure(p) => 0xA0 | p.channel,
+            Event::ControllerChange(c) => 0xB0 | c.channel,
+            Event::ProgramChange(p) => 0xC0 | p.channel,
+            Event::ChannelPressure(c) => 0xD0 | c.channel,
+            Event::PitchBend(p) => 0xE0 | p.channel,
+            _ => return None,
+        })
+    }
+
+    /// Returns the MIDI Channel of the event if it is a MIDI Channel Event
+    pub fn channel(&self) -> Option<u8> {
+        self.status_byte().map(|status| status & 0x0F)
+    }
+
     /// Read an event from a binary file
-    pub fn import<R: Read + Seek>(
+    pub fn import<R: Read>(reader: &mut R, previous_code_byte: Option<u8>) -> Result<(Event, u8)> {
+        Event::import_with_options(reader, previous_code_byte, &ImportOptions::default())
+    }
+
+    /// Read an event from a binary file, with limits on the data of SysEx and Meta Events
+    pub fn import_with_options<R: Read>(
+        reader: &mut R,
+        previous_code_byte: Option<u8>,
+        options: &ImportOptions,
+    ) -> Result<(Event, u8)> {
+        let limits = DataLimits {
+            max_event_data: options.max_event_data,
+            remaining: None,
+            skip_payloads: options.skip_payloads,
+        };
+        Event::import_limited(reader, previous_code_byte, limits)
+    }
+
+    /// Read an event from a binary file, checking the sizes declared by SysEx and Meta Events before allocating anything
+    pub(crate) fn import_limited<R: Read>(
         reader: &mut R,
         previous_code_byte: Option<u8>,
+        limits: DataLimits,
     ) -> Result<(Event, u8)> {
         let mut code_byte = reader.read_to_u8()?;
         let next_byte;
@@ -103,6 +269,10 @@ impl Event {
                 None => return Err(SMFError::NoPreviousEvent),
             }
         } else {
+            // System Common and System Real-Time messages have no place in a MIDI File
+            if let 0xF1..=0xF6 | 0xF8..=0xFE = code_byte {
+                return Err(SMFError::InvalidInSmf(code_byte));
+            }
             next_byte = reader.read_to_u8()?;
         }
         let new_event = match (code_byte >> 4) & 0b0000_1111 {
@@ -118,19 +288,27 @@ impl Event {
             0b1101 => Event::ChannelPressure(ChannelPressure::import(code_byte, next_byte)),
             0b1110 => Event::PitchBend(PitchBend::import(reader, code_byte, next_byte)?),
             0b1111 => match code_byte & 0b0000_1111 {
-                0b0000 => Event::SystemExclusiveF0(SystemExclusive::import(reader, next_byte)?),
-                0b0111 => Event::SystemExclusiveF7(SystemExclusive::import(reader, next_byte)?),
+                0b0000 => Event::SystemExclusiveF0(SystemExclusive::import_limited(
+                    reader, next_byte, limits,
+                )?),
+                0b0111 => Event::SystemExclusiveF7(SystemExclusive::import_limited(
+                    reader, next_byte, limits,
+                )?),
                 0b1111 => match next_byte {
                     0 => Event::SequenceNumber(SequenceNumber::import(reader)?),
-                    1 => Event::Text(TextMessage::import(reader)?),
-                    2 => Event::Copyright(TextMessage::import(reader)?),
-                    3 => Event::SequenceTrackName(TextMessage::import(reader)?),
-                    4 => Event::InstrumentName(TextMessage::import(reader)?),
-                    5 => Event::Lyric(TextMessage::import(reader)?),
-                    6 => Event::Marker(TextMessage::import(reader)?),
-                    7 => Event::CuePoint(TextMessage::import(reader)?),
-                    8 => Event::ProgramName(TextMessage::import(reader)?),
-                    9 => Event::DeviceName(TextMessage::import(reader)?),
+                    1 => Event::Text(TextMessage::import_limited(reader, limits)?),
+                    2 => Event::Copyright(TextMessage::import_limited(reader, limits)?),
+                    3 => Event::SequenceTrackName(TextMessage::import_limited(reader, limits)?),
+                    4 => Event::InstrumentName(TextMessage::import_limited(reader, limits)?),
+                    5 => Event::Lyric(TextMessage::import_limited(reader, limits)?),
+                    6 => Event::Marker(TextMessage::import_limited(reader, limits)?),
+                    7 => Event::CuePoint(TextMessage::import_limited(reader, limits)?),
+                    8 => Event::ProgramName(TextMessage::import_limited(reader, limits)?),
+                    9 => Event::DeviceName(TextMessage::import_limited(reader, limits)?),
+                    0x0A..=0x0F => Event::OtherText(OtherText {
+                        meta_type: next_byte,
+                        message: TextMessage::import_limited(reader, limits)?,
+                    }),
                     0x20 => Event::MIDIChannelPrefix(MIDIChannelPrefix::import(reader)?),
                     0x21 => Event::MIDIPort(MIDIPort::import(reader)?),
                     0x2F => Event::EndOfTrack(EndOfTrack::import(reader)?),
@@ -138,7 +316,12 @@ impl Event {
                     0x54 => Event::SMPTEOffset(SMPTEOffset::import(reader)?),
                     0x58 => Event::TimeSignature(TimeSignature::import(reader)?),
                     0x59 => Event::KeySignature(KeySignature::import(reader)?),
-                    _ => Event::UnknownMetaEvent(GenericMetaEvent::import(reader)?),
+                    0x7F => Event::SequencerSpecificEvent(GenericMetaEvent::import_limited(
+                        reader, 0x7F, limits,
+                    )?),
+                    x => Event::UnknownMetaEvent(GenericMetaEvent::import_limited(
+                        reader, x, limits,
+                    )?),
                 },
                 _ => return Err(SMFError::UnknownEvent(code_byte)),
             },
@@ -147,15 +330,150 @@ impl Event {
         Ok((new_event, code_byte))
     }
 
-    /// Write the event in its binary form
+    /// Write the event in its binary form. Running Status is never used.
     pub fn export<W: Write>(&self, writer: &mut W) -> Result<()> {
-        unimplemented!();
+        match self {
+            Event::NoteOff(n) => n.export(writer, 0x80),
+            Event::NoteOn(n) => n.export(writer, 0x90),
+            Event::PolyphonicKeyPressure(p) => p.export(writer),
+            Event::ControllerChange(c) => c.export(writer),
+            Event::ProgramChange(p) => p.export(writer),
+            Event::ChannelPressure(c) => c.export(writer),
+            Event::PitchBend(p) => p.export(writer),
+            Event::SystemExclusiveF0(s) => s.export(writer, 0xF0),
+            Event::SystemExclusiveF7(s) => s.export(writer, 0xF7),
+            Event::SequenceNumber(s) => s.export(writer),
+            Event::Text(t) => t.export(writer, 1),
+            Event::Copyright(c) => c.export(writer, 2),
+            Event::SequenceTrackName(s) => s.export(writer, 3),
+            Event::InstrumentName(i) => i.export(writer, 4),
+            Event::Lyric(l) => l.export(writer, 5),
+            Event::Marker(m) => m.export(writer, 6),
+            Event::CuePoint(c) => c.export(writer, 7),
+            Event::ProgramName(p) => p.export(writer, 8),
+            Event::DeviceName(d) => d.export(writer, 9),
+            Event::OtherText(o) => o.message.export(writer, o.meta_type),
+            Event::MIDIChannelPrefix(m) => m.export(writer),
+            Event::MIDIPort(m) => m.export(writer),
+            Event::EndOfTrack(e) => e.export(writer),
+            Event::Tempo(t) => t.export(writer),
+            Event::SMPTEOffset(s) => s.export(writer),
+            Event::TimeSignature(t) => t.export(writer),
+            Event::KeySignature(k) => k.export(writer),
+            Event::SequencerSpecificEvent(s) => s.export_as(writer, 0x7F),
+            Event::UnknownMetaEvent(u) => u.export(writer),
+        }
+    }
+
+    /// Writes the event (status byte and data, no Delta-Time) to a new buffer
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(self.get_length()? as usize);
+        self.export(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Describes this event for humans. Channels are counted from 1 and keys are named with middle C (60) being C4.
+    pub fn describe(&self, verbosity: Verbosity) -> String {
+        let brief = format!("{:?}", self.kind());
+        if verbosity == Verbosity::Brief {
+            return brief;
+        }
+        let details = match self {
+            Event::NoteOff(n) | Event::NoteOn(n) => {
+                format!("ch{} {}", u16::from(n.channel) + 1, note_name(n.key))
+            }
+            Event::PolyphonicKeyPressure(p) => {
+                format!(
+                    "ch{} {} {}",
+                    u16::from(p.channel) + 1,
+                    note_name(p.key),
+                    p.pressure
+                )
+            }
+            Event::ControllerChange(c) => {
+                format!(
+                    "ch{} #{} = {}",
+                    u16::from(c.channel) + 1,
+                    c.controller_number,
+                    c.value
+                )
+            }
+            Event::ProgramChange(p) => format!("ch{} {}", u16::from(p.channel) + 1, p.program),
+            Event::ChannelPressure(c) => format!("ch{} {}", u16::from(c.channel) + 1, c.pressure),
+            Event::PitchBend(p) => format!("ch{} {}", u16::from(p.channel) + 1, p.value),
+            Event::SystemExclusiveF0(s) | Event::SystemExclusiveF7(s) => {
+                format!("{} bytes", s.data.len())
+            }
+            Event::SequenceNumber(s) => s.sequence_number.to_string(),
+            Event::OtherText(o) => format!("0x{:02X} {:?}", o.meta_type, o.message.text_cow()),
+            Event::MIDIChannelPrefix(m) => format!("ch{}", u16::from(m.channel) + 1),
+            Event::MIDIPort(m) => m.port.to_string(),
+            Event::EndOfTrack(_) => String::new(),
+            Event::Tempo(t) => format!("{} ({} BPM)", t.value, 60_000_000.0 / f64::from(t.value)),
+            Event::SMPTEOffset(s) => s.to_timecode(),
+            Event::TimeSignature(t) => format!(
+                "{}/{}",
+                t.numerator,
+                2u32.saturating_pow(u32::from(t.denominator))
+            ),
+            Event::KeySignature(k) => format!("{} {:?}", k.flats_sharps, k.key),
+            Event::SequencerSpecificEvent(g) | Event::UnknownMetaEvent(g) => {
+                format!("0x{:02X} {} bytes", g.meta_type, g.data.len())
+            }
+            // Every other event is text
+            _ => match self.text_message() {
+                Some(t) => format!("{:?}", t.text_cow()),
+                None => String::new(),
+            },
+        };
+        let normal = if details.is_empty() {
+            brief
+        } else {
+            format!("{} {}", brief, details)
+        };
+        if verbosity == Verbosity::Normal {
+            return normal;
+        }
+        let bytes = match self.to_bytes() {
+            Ok(bytes) => bytes
+                .iter()
+                .map(|b| format!("{:02X}", b))
+                .collect::<Vec<String>>()
+                .join(" "),
+            Err(e) => format!("unwritable: {}", e),
+        };
+        format!("{} {:?} [{}]", normal, self, bytes)
     }
 }
 
+/// How much `Event::describe` tells about an event
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Verbosity {
+    /// Only the kind of event, e.g. "NoteOn"
+    Brief,
+    /// The kind and the main values, e.g. "NoteOn ch1 C4"
+    Normal,
+    /// Everything, along with the bytes of the event in hexadecimal
+    Verbose,
+}
+
+/// Names of the 12 pitch classes, starting from C
+const NOTE_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+/// Name of a key along with its octave, middle C (60) being C4
+fn note_name(key: u8) -> String {
+    format!(
+        "{}{}",
+        NOTE_NAMES[usize::from(key % 12)],
+        i16::from(key / 12) - 1
+    )
+}
+
 // MIDI Events
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct NoteChange {
     pub channel: u8,
     pub key: u8,
@@ -176,9 +494,18 @@ impl NoteChange {
             velocity,
         })
     }
+    pub fn export<W: Write>(self, writer: &mut W, code_byte: u8) -> Result<()> {
+        writer.write_all(&[code_byte | self.channel, self.key, self.velocity])?;
+        Ok(())
+    }
+    /// Returns the live MIDI message for this note, as a Note On or a Note Off
+    pub fn to_midi_bytes(self, on: bool) -> [u8; 3] {
+        let code_byte = if on { 0x90 } else { 0x80 };
+        [code_byte | self.channel, self.key, self.velocity]
+    }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct PolyphonicKeyPressure {
     pub channel: u8,
     pub key: u8,
@@ -203,9 +530,17 @@ impl PolyphonicKeyPressure {
             pressure,
         })
     }
+    pub fn export<W: Write>(self, writer: &mut W) -> Result<()> {
+        writer.write_all(&self.to_midi_bytes())?;
+        Ok(())
+    }
+    /// Returns the live MIDI message for this event
+    pub fn to_midi_bytes(self) -> [u8; 3] {
+        [0xA0 | self.channel, self.key, self.pressure]
+    }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct ControllerChange {
     pub channel: u8,
     pub controller_number: u8,
@@ -230,9 +565,17 @@ impl ControllerChange {
             value,
         })
     }
+    pub fn export<W: Write>(self, writer: &mut W) -> Result<()> {
+        writer.write_all(&self.to_midi_bytes())?;
+        Ok(())
+    }
+    /// Returns the live MIDI message for this event
+    pub fn to_midi_bytes(self) -> [u8; 3] {
+        [0xB0 | self.channel, self.controller_number, self.value]
+    }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct ProgramChange {
     pub channel: u8,
     pub program: u8,
@@ -247,9 +590,17 @@ impl ProgramChange {
         let program = next_byte;
         ProgramChange { channel, program }
     }
+    pub fn export<W: Write>(self, writer: &mut W) -> Result<()> {
+        writer.write_all(&self.to_midi_bytes())?;
+        Ok(())
+    }
+    /// Returns the live MIDI message for this event
+    pub fn to_midi_bytes(self) -> [u8; 2] {
+        [0xC0 | self.channel, self.program]
+    }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct ChannelPressure {
     pub channel: u8,
     pub pressure: u8,
@@ -264,11 +615,20 @@ impl ChannelPressure {
         let pressure = next_byte;
         ChannelPressure { channel, pressure }
     }
+    pub fn export<W: Write>(self, writer: &mut W) -> Result<()> {
+        writer.write_all(&self.to_midi_bytes())?;
+        Ok(())
+    }
+    /// Returns the live MIDI message for this event
+    pub fn to_midi_bytes(self) -> [u8; 2] {
+        [0xD0 | self.channel, self.pressure]
+    }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct PitchBend {
     pub channel: u8,
+    /// 14-bit value, from 0 to 0x3FFF. 0x2000 means no bend.
     pub value: u16,
 }
 
@@ -278,14 +638,29 @@ impl PitchBend {
     }
     pub fn import<R: Read>(reader: &mut R, code_byte: u8, next_byte: u8) -> Result<PitchBend> {
         let channel = code_byte & 0b0000_1111;
-        let value = u16::from(reader.read_to_u8()?) << 8 | u16::from(next_byte); // Little Endian here, confirmed by two websites... Weird
+        // Two 7-bit data bytes, least significant first
+        let lsb = next_byte & 0x7F;
+        let msb = reader.read_to_u8()? & 0x7F;
+        let value = (u16::from(msb) << 7) | u16::from(lsb);
         Ok(PitchBend { channel, value })
     }
+    pub fn export<W: Write>(self, writer: &mut W) -> Result<()> {
+        writer.write_all(&self.to_midi_bytes())?;
+        Ok(())
+    }
+    /// Returns the live MIDI message for this event
+    pub fn to_midi_bytes(self) -> [u8; 3] {
+        [
+            0xE0 | self.channel,
+            (self.value & 0x7F) as u8,
+            ((self.value >> 7) & 0x7F) as u8,
+        ]
+    }
 }
 
 // System Exclusive
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct SystemExclusive {
     pub length: VLV,
     pub data: Vec<u8>,
@@ -296,16 +671,73 @@ impl SystemExclusive {
         Ok(1 + u32::from(self.length.get_length()?) + self.data.len() as u32)
     }
     pub fn import<R: Read>(reader: &mut R, next_byte: u8) -> Result<SystemExclusive> {
-        let length = VLV::partial_import(reader, next_byte)?;
-        let mut data = vec![0u8; length.value as usize];
-        reader.read_exact(&mut data)?;
+        SystemExclusive::import_limited(reader, next_byte, DataLimits::default())
+    }
+    pub(crate) fn import_limited<R: Read>(
+        reader: &mut R,
+        next_byte: u8,
+        limits: DataLimits,
+    ) -> Result<SystemExclusive> {
+        let mut length = VLV::partial_import(reader, next_byte)?;
+        let data = read_data(reader, &mut length, limits)?;
         Ok(SystemExclusive { length, data })
     }
+    /// Reads the Manufacturer ID at the start of the message.
+    /// Continuation packets (F7 events not starting with 0xF0) do not carry one, so this returns None for them.
+    pub fn manufacturer(&self, continuation: bool) -> Option<ManufacturerId> {
+        let data = if continuation {
+            match self.data.split_first() {
+                Some((0xF0, rest)) => rest,
+                _ => return None,
+            }
+        } else {
+            &self.data[..]
+        };
+        match data {
+            [0, a, b, ..] => Some(ManufacturerId::Extended(*a, *b)),
+            [0, ..] => None,
+            [id, ..] => Some(ManufacturerId::Single(*id)),
+            [] => None,
+        }
+    }
+    /// Splits this message into packets of at most `max_packet` data bytes, for devices with small buffers.
+    /// The first packet is meant to be written as a SystemExclusiveF0 and the others as SystemExclusiveF7 continuation packets.
+    /// The last packet always ends with 0xF7, which is added if this message did not end with it.
+    /// Fails if a packet is too big for its length to fit in a VLV.
+    pub fn split(&self, max_packet: usize) -> Result<Vec<SystemExclusive>> {
+        let mut data = self.data.clone();
+        if data.last() != Some(&0xF7) {
+            data.push(0xF7);
+        }
+        data.chunks(max_packet.max(1))
+            .map(|chunk| {
+                Ok(SystemExclusive {
+                    length: VLV::new(u32::try_from(chunk.len()).unwrap_or(u32::MAX))?,
+                    data: chunk.to_vec(),
+                })
+            })
+            .collect()
+    }
+    pub fn export<W: Write>(&self, writer: &mut W, code_byte: u8) -> Result<()> {
+        writer.write_to_u8(code_byte)?;
+        self.length.export(writer)?;
+        writer.write_all(&self.data)?;
+        Ok(())
+    }
+}
+
+/// Identifies the maker of the device targeted by a SysEx message.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ManufacturerId {
+    /// One byte ID, e.g. 0x41 for Roland or 0x43 for Yamaha
+    Single(u8),
+    /// Three byte ID, starting with 0x00 followed by these two bytes
+    Extended(u8, u8),
 }
 
 // Meta Event
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct SequenceNumber {
     pub sequence_number: u16,
 }
@@ -314,43 +746,102 @@ impl SequenceNumber {
     pub fn get_length(self) -> u32 {
         5
     }
-    pub fn import<R: Read + Seek>(reader: &mut R) -> Result<SequenceNumber> {
+    pub fn import<R: Read>(reader: &mut R) -> Result<SequenceNumber> {
         // Read VLV
         let length = VLV::import(reader)?;
-        // If length is smaller than 2 error
-        if length.value < 2 {
-            return Err(SMFError::UnexpectedMetaEventLength(length.value));
-        }
         // Read the data
         let sequence_number = reader.read_be_to_u16()?;
-        // If Length is bigger than 2 then discard the extra data
-        if length.value > 2 {
-            reader.seek(SeekFrom::Current(i64::from(length.value - 2)))?;
-        }
+        // Check the length and discard the extra data
+        skip_remaining(reader, length.value, 2)?;
         Ok(SequenceNumber { sequence_number })
     }
+    pub fn export<W: Write>(self, writer: &mut W) -> Result<()> {
+        writer.write_all(&[0xFF, 0x00, 2])?;
+        writer.write_be_to_u16(self.sequence_number)?;
+        Ok(())
+    }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct TextMessage {
     pub length: VLV,
+    /// The text, decoded as UTF-8. Invalid sequences are replaced, see `raw`.
     pub text: String,
+    /// The original bytes when they were not valid UTF-8, written back instead of the text to keep the file unchanged. Use `set_text` to change the text of such a message.
+    pub raw: Option<Vec<u8>>,
 }
 
 impl TextMessage {
+    /// Creates a message from a text
+    pub fn new(text: &str) -> Result<TextMessage> {
+        TextMessage::from_bytes(text.as_bytes().to_vec())
+    }
+    /// Creates a message from the bytes of a text, keeping them if they are not valid UTF-8
+    pub fn from_bytes(data: Vec<u8>) -> Result<TextMessage> {
+        let length = VLV::new(u32::try_from(data.len()).unwrap_or(u32::MAX))?;
+        let (text, raw) = decode_text(data);
+        Ok(TextMessage { length, text, raw })
+    }
     pub fn get_length(&self) -> Result<u32> {
-        Ok(2 + u32::from(self.length.get_length()?) + self.text.len() as u32)
+        Ok(2 + u32::from(self.length.get_length()?) + self.bytes().len() as u32)
     }
     pub fn import<R: Read>(reader: &mut R) -> Result<TextMessage> {
-        let length = VLV::import(reader)?;
-        let mut data = vec![0u8; length.value as usize];
-        reader.read_exact(&mut data)?;
-        let text = String::from_utf8_lossy(&data).into_owned();
-        Ok(TextMessage { length, text })
+        TextMessage::import_limited(reader, DataLimits::default())
+    }
+    pub(crate) fn import_limited<R: Read>(
+        reader: &mut R,
+        limits: DataLimits,
+    ) -> Result<TextMessage> {
+        let mut length = VLV::import(reader)?;
+        let data = read_data(reader, &mut length, limits)?;
+        let (text, raw) = decode_text(data);
+        Ok(TextMessage { length, text, raw })
+    }
+    pub fn export<W: Write>(&self, writer: &mut W, meta_type: u8) -> Result<()> {
+        writer.write_all(&[0xFF, meta_type])?;
+        self.length.export(writer)?;
+        writer.write_all(self.bytes())?;
+        Ok(())
+    }
+    /// Replaces the text, updating the length and forgetting the original bytes
+    pub fn set_text(&mut self, text: String) -> Result<()> {
+        self.length = VLV::new(u32::try_from(text.len()).unwrap_or(u32::MAX))?;
+        self.text = text;
+        self.raw = None;
+        Ok(())
     }
+    /// The bytes of this message as they are written in a file
+    pub fn bytes(&self) -> &[u8] {
+        match &self.raw {
+            Some(raw) => raw,
+            None => self.text.as_bytes(),
+        }
+    }
+    /// Returns the text, only allocating when the original bytes were not valid UTF-8 and need to be converted
+    pub fn text_cow(&self) -> Cow<'_, str> {
+        match &self.raw {
+            Some(raw) => String::from_utf8_lossy(raw),
+            None => Cow::Borrowed(&self.text),
+        }
+    }
+    /// Returns the text without its C0 control characters (0x00 to 0x1F) except newlines, so it can be safely printed.
+    pub fn clean_text(&self) -> String {
+        self.text
+            .chars()
+            .filter(|c| *c == '\n' || *c as u32 >= 0x20)
+            .collect()
+    }
+}
+
+/// A Text Event of a type without a defined meaning, keeping its type so it can be written back
+#[derive(Clone, Debug, PartialEq)]
+pub struct OtherText {
+    /// The byte following 0xFF, from 0x0A to 0x0F
+    pub meta_type: u8,
+    pub message: TextMessage,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct MIDIChannelPrefix {
     pub channel: u8,
 }
@@ -359,24 +850,22 @@ impl MIDIChannelPrefix {
     pub fn get_length(self) -> u32 {
         4
     }
-    pub fn import<R: Read + Seek>(reader: &mut R) -> Result<MIDIChannelPrefix> {
+    pub fn import<R: Read>(reader: &mut R) -> Result<MIDIChannelPrefix> {
         // Read VLV
         let length = VLV::import(reader)?;
-        // If length is smaller than 1 error
-        if length.value < 1 {
-            return Err(SMFError::UnexpectedMetaEventLength(length.value));
-        }
         // Read the data
         let channel = reader.read_to_u8()?;
-        // If Length is bigger than 1 then discard the extra data
-        if length.value > 1 {
-            reader.seek(SeekFrom::Current(i64::from(length.value - 1)))?;
-        }
+        // Check the length and discard the extra data
+        skip_remaining(reader, length.value, 1)?;
         Ok(MIDIChannelPrefix { channel })
     }
+    pub fn export<W: Write>(self, writer: &mut W) -> Result<()> {
+        writer.write_all(&[0xFF, 0x20, 1, self.channel])?;
+        Ok(())
+    }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct MIDIPort {
     pub port: u8,
 }
@@ -385,70 +874,88 @@ impl MIDIPort {
     pub fn get_length(self) -> u32 {
         4
     }
-    pub fn import<R: Read + Seek>(reader: &mut R) -> Result<MIDIPort> {
+    pub fn import<R: Read>(reader: &mut R) -> Result<MIDIPort> {
         // Read VLV
         let length = VLV::import(reader)?;
-        // If length is smaller than 1 error
-        if length.value < 1 {
-            return Err(SMFError::UnexpectedMetaEventLength(length.value));
-        }
         // Read the data
         let port = reader.read_to_u8()?;
-        // If Length is bigger than 1 then discard the extra data
-        if length.value > 1 {
-            reader.seek(SeekFrom::Current(i64::from(length.value - 1)))?;
-        }
+        // Check the length and discard the extra data
+        skip_remaining(reader, length.value, 1)?;
         Ok(MIDIPort { port })
     }
+    pub fn export<W: Write>(self, writer: &mut W) -> Result<()> {
+        writer.write_all(&[0xFF, 0x21, 1, self.port])?;
+        Ok(())
+    }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct EndOfTrack {}
 
 impl EndOfTrack {
     pub fn get_length(self) -> u32 {
         3
     }
-    pub fn import<R: Read + Seek>(reader: &mut R) -> Result<EndOfTrack> {
+    pub fn import<R: Read>(reader: &mut R) -> Result<EndOfTrack> {
         // Read VLV
         let length = VLV::import(reader)?;
-        // If Length is different than 0 then discard the extra data
-        if length.value != 0 {
-            reader.seek(SeekFrom::Current(i64::from(length.value)))?;
-        }
+        // Discard the data, if any
+        skip_remaining(reader, length.value, 0)?;
         Ok(EndOfTrack {})
     }
+    pub fn export<W: Write>(self, writer: &mut W) -> Result<()> {
+        writer.write_all(&[0xFF, 0x2F, 0])?;
+        Ok(())
+    }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Tempo {
     pub value: u32,
 }
 
 impl Tempo {
+    /// Tempo in effect until the first Tempo event, 120 BPM
+    pub const DEFAULT: u32 = 500_000;
+    /// Makes a Tempo from a number of Beats (Quarter Notes) per Minute, rounded to the nearest microsecond.
+    /// A Tempo holds 24 bits, so anything slower than about 3.58 BPM fails with TempoOutOfRange, as does a BPM that is not a positive number.
+    pub fn from_bpm(bpm: f64) -> Result<Tempo> {
+        let value = (60_000_000.0 / bpm).round();
+        if !(1.0..=f64::from(0xFF_FFFFu32)).contains(&value) {
+            return Err(SMFError::TempoOutOfRange(bpm));
+        }
+        Ok(Tempo {
+            value: value as u32,
+        })
+    }
     pub fn get_length(self) -> u32 {
         6
     }
-    pub fn import<R: Read + Seek>(reader: &mut R) -> Result<Tempo> {
+    pub fn import<R: Read>(reader: &mut R) -> Result<Tempo> {
         // Read VLV
         let length = VLV::import(reader)?;
-        // If length is smaller than 3 error
-        if length.value < 3 {
-            return Err(SMFError::UnexpectedMetaEventLength(length.value));
-        }
         // Read the data
         let value = u32::from(reader.read_to_u8()?) << 16
             | u32::from(reader.read_to_u8()?) << 8
             | u32::from(reader.read_to_u8()?);
-        // If Length is bigger than 3 then discard the extra data
-        if length.value > 3 {
-            reader.seek(SeekFrom::Current(i64::from(length.value - 1)))?;
-        }
+        // Check the length and discard the extra data
+        skip_remaining(reader, length.value, 3)?;
         Ok(Tempo { value })
     }
+    pub fn export<W: Write>(self, writer: &mut W) -> Result<()> {
+        writer.write_all(&[
+            0xFF,
+            0x51,
+            3,
+            (self.value >> 16) as u8,
+            (self.value >> 8) as u8,
+            self.value as u8,
+        ])?;
+        Ok(())
+    }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct SMPTEOffset {
     pub hours: u8,
     pub minutes: u8,
@@ -461,23 +968,17 @@ impl SMPTEOffset {
     pub fn get_length(self) -> u32 {
         8
     }
-    pub fn import<R: Read + Seek>(reader: &mut R) -> Result<SMPTEOffset> {
+    pub fn import<R: Read>(reader: &mut R) -> Result<SMPTEOffset> {
         // Read VLV
         let length = VLV::import(reader)?;
-        // If length is smaller than 5 error
-        if length.value < 5 {
-            return Err(SMFError::UnexpectedMetaEventLength(length.value));
-        }
         // Read the data
         let hours = reader.read_to_u8()?;
         let minutes = reader.read_to_u8()?;
         let seconds = reader.read_to_u8()?;
         let frames = reader.read_to_u8()?;
         let fractional_frames = reader.read_to_u8()?;
-        // If Length is bigger than 5 then discard the extra data
-        if length.value > 5 {
-            reader.seek(SeekFrom::Current(i64::from(length.value - 1)))?;
-        }
+        // Check the length and discard the extra data
+        skip_remaining(reader, length.value, 5)?;
         Ok(SMPTEOffset {
             hours,
             minutes,
@@ -486,46 +987,199 @@ impl SMPTEOffset {
             fractional_frames,
         })
     }
+    /// Converts this offset to seconds, given the frame rate. Fractional frames are 1/100th of a frame.
+    /// The frame rate bits stored in the upper part of the hours byte are ignored.
+    pub fn as_seconds(self, fps: f64) -> f64 {
+        f64::from(self.hours & 0b0001_1111) * 3600.0
+            + f64::from(self.minutes) * 60.0
+            + f64::from(self.seconds)
+            + (f64::from(self.frames) + f64::from(self.fractional_frames) / 100.0) / fps
+    }
+    /// Creates an offset from seconds, given the frame rate. The offset must be less than 24 hours.
+    pub fn from_seconds(seconds: f64, fps: f64) -> Result<SMPTEOffset> {
+        if !(0.0..86400.0).contains(&seconds) || fps <= 0.0 {
+            return Err(SMFError::SMPTEOffsetOutOfRange);
+        }
+        let mut whole_seconds = seconds.trunc() as u32;
+        // Rest of the offset in 1/100th of a frame
+        let mut hundredths = ((seconds - seconds.trunc()) * fps * 100.0).round() as u32;
+        if f64::from(hundredths) >= fps * 100.0 {
+            // Rounding brought us to the next second
+            whole_seconds += 1;
+            hundredths = 0;
+        }
+        if whole_seconds >= 86400 {
+            return Err(SMFError::SMPTEOffsetOutOfRange);
+        }
+        Ok(SMPTEOffset {
+            hours: (whole_seconds / 3600) as u8,
+            minutes: ((whole_seconds / 60) % 60) as u8,
+            seconds: (whole_seconds % 60) as u8,
+            frames: (hundredths / 100) as u8,
+            fractional_frames: (hundredths % 100) as u8,
+        })
+    }
+    /// Parses a timecode written as "HH:MM:SS:FF.ff", the fractional frames being optional.
+    /// The frame rate is stored in the upper bits of the hours byte, as the spec asks, and every field is checked against it.
+    pub fn from_timecode(timecode: &str, rate: SmpteFrameRate) -> Result<SMPTEOffset> {
+        let invalid = || SMFError::InvalidTimecode(timecode.to_string());
+        let parts: Vec<&str> = timecode.split(':').collect();
+        if parts.len() != 4 {
+            return Err(invalid());
+        }
+        let (frames, fractional_frames) = match parts[3].split_once('.') {
+            Some((frames, fractional)) => (frames, fractional),
+            None => (parts[3], "0"),
+        };
+        let mut fields = [0u8; 5];
+        for (field, text) in fields
+            .iter_mut()
+            .zip([parts[0], parts[1], parts[2], frames, fractional_frames].iter())
+        {
+            *field = text.parse().map_err(|_| invalid())?;
+        }
+        let [hours, minutes, seconds, frames, fractional_frames] = fields;
+        if hours >= 24
+            || minutes >= 60
+            || seconds >= 60
+            || frames >= rate.frames_per_second()
+            || fractional_frames >= 100
+        {
+            return Err(SMFError::SMPTEOffsetOutOfRange);
+        }
+        Ok(SMPTEOffset {
+            hours: hours | rate.bits() << 5,
+            minutes,
+            seconds,
+            frames,
+            fractional_frames,
+        })
+    }
+    /// Writes this offset as a "HH:MM:SS:FF.ff" timecode
+    pub fn to_timecode(self) -> String {
+        format!(
+            "{:02}:{:02}:{:02}:{:02}.{:02}",
+            self.hours & 0b0001_1111,
+            self.minutes,
+            self.seconds,
+            self.frames,
+            self.fractional_frames
+        )
+    }
+    /// Reads the frame rate stored in the upper bits of the hours byte
+    pub fn frame_rate(self) -> SmpteFrameRate {
+        match (self.hours >> 5) & 0b11 {
+            0 => SmpteFrameRate::Fps24,
+            1 => SmpteFrameRate::Fps25,
+            2 => SmpteFrameRate::Fps29_97,
+            _ => SmpteFrameRate::Fps30,
+        }
+    }
+    pub fn export<W: Write>(self, writer: &mut W) -> Result<()> {
+        writer.write_all(&[
+            0xFF,
+            0x54,
+            5,
+            self.hours,
+            self.minutes,
+            self.seconds,
+            self.frames,
+            self.fractional_frames,
+        ])?;
+        Ok(())
+    }
 }
 
-#[derive(Copy, Clone)]
+/// The frame rates a SMPTE Offset can use
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SmpteFrameRate {
+    Fps24,
+    Fps25,
+    /// 30 fps drop frame
+    Fps29_97,
+    Fps30,
+}
+
+impl SmpteFrameRate {
+    /// Number of frames counted in a second, 30 for drop frame
+    pub fn frames_per_second(self) -> u8 {
+        match self {
+            SmpteFrameRate::Fps24 => 24,
+            SmpteFrameRate::Fps25 => 25,
+            SmpteFrameRate::Fps29_97 | SmpteFrameRate::Fps30 => 30,
+        }
+    }
+    /// Value stored in bits 5 and 6 of the hours of a SMPTE Offset
+    fn bits(self) -> u8 {
+        match self {
+            SmpteFrameRate::Fps24 => 0,
+            SmpteFrameRate::Fps25 => 1,
+            SmpteFrameRate::Fps29_97 => 2,
+            SmpteFrameRate::Fps30 => 3,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct TimeSignature {
     pub numerator: u8,
+    /// Power of 2 of the denominator, e.g. 3 for eighth notes
     pub denominator: u8,
     pub clocks_between_metronome_clicks: u8,
-    pub yes: u8,
+    /// Number of 32nd notes in a MIDI quarter note (24 MIDI Clocks), almost always 8. This field used to be named `yes`.
+    pub thirty_seconds_per_quarter: u8,
 }
 
 impl TimeSignature {
+    /// Creates a Time Signature. The denominator is given as a power of 2, e.g. 6/8 is `new(6, 3, 36, 8)`.
+    pub fn new(
+        numerator: u8,
+        denominator_pow2: u8,
+        clocks_between_metronome_clicks: u8,
+        thirty_seconds_per_quarter: u8,
+    ) -> TimeSignature {
+        TimeSignature {
+            numerator,
+            denominator: denominator_pow2,
+            clocks_between_metronome_clicks,
+            thirty_seconds_per_quarter,
+        }
+    }
     pub fn get_length(self) -> u32 {
         7
     }
-    pub fn import<R: Read + Seek>(reader: &mut R) -> Result<TimeSignature> {
+    pub fn import<R: Read>(reader: &mut R) -> Result<TimeSignature> {
         // Read VLV
         let length = VLV::import(reader)?;
-        // If length is smaller than 4 error
-        if length.value < 4 {
-            return Err(SMFError::UnexpectedMetaEventLength(length.value));
-        }
         // Read the data
         let numerator = reader.read_to_u8()?;
         let denominator = reader.read_to_u8()?;
         let clocks_between_metronome_clicks = reader.read_to_u8()?;
-        let yes = reader.read_to_u8()?;
-        // If Length is bigger than 4 then discard the extra data
-        if length.value > 4 {
-            reader.seek(SeekFrom::Current(i64::from(length.value - 1)))?;
-        }
+        let thirty_seconds_per_quarter = reader.read_to_u8()?;
+        // Check the length and discard the extra data
+        skip_remaining(reader, length.value, 4)?;
         Ok(TimeSignature {
             numerator,
             denominator,
             clocks_between_metronome_clicks,
-            yes,
+            thirty_seconds_per_quarter,
         })
     }
+    pub fn export<W: Write>(self, writer: &mut W) -> Result<()> {
+        writer.write_all(&[
+            0xFF,
+            0x58,
+            4,
+            self.numerator,
+            self.denominator,
+            self.clocks_between_metronome_clicks,
+            self.thirty_seconds_per_quarter,
+        ])?;
+        Ok(())
+    }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct KeySignature {
     pub flats_sharps: i8,
     pub key: Key,
@@ -535,26 +1189,26 @@ impl KeySignature {
     pub fn get_length(self) -> u32 {
         5
     }
-    pub fn import<R: Read + Seek>(reader: &mut R) -> Result<KeySignature> {
+    pub fn import<R: Read>(reader: &mut R) -> Result<KeySignature> {
         // Read VLV
         let length = VLV::import(reader)?;
-        // If length is smaller than 1 error
-        if length.value < 2 {
-            return Err(SMFError::UnexpectedMetaEventLength(length.value));
-        }
         // Read the data
         let flats_sharps = reader.read_to_i8()?;
         let key = Key::import(reader)?;
-        // If Length is bigger than 1 then discard the extra data
-        if length.value > 2 {
-            reader.seek(SeekFrom::Current(i64::from(length.value - 1)))?;
-        }
+        // Check the length and discard the extra data
+        skip_remaining(reader, length.value, 2)?;
         Ok(KeySignature { flats_sharps, key })
     }
+    pub fn export<W: Write>(self, writer: &mut W) -> Result<()> {
+        writer.write_all(&[0xFF, 0x59, 2])?;
+        writer.write_to_i8(self.flats_sharps)?;
+        self.key.export(writer)?;
+        Ok(())
+    }
 }
 
 // Not an event !
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Key {
     Major,
     Minor,
@@ -578,20 +1232,107 @@ impl Key {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct GenericMetaEvent {
+    /// The byte following 0xFF, telling what kind of Meta Event this is
+    pub meta_type: u8,
     pub length: VLV,
     pub data: Vec<u8>,
 }
 
 impl GenericMetaEvent {
     pub fn get_length(&self) -> Result<u32> {
-        Ok(2 + u32::from(self.length.get_length()?) + self.data.len() as u32)
+        Ok(2 + u32::from(self.data_length()?.get_length()?) + self.data.len() as u32)
     }
-    pub fn import<R: Read>(reader: &mut R) -> Result<GenericMetaEvent> {
-        let length = VLV::import(reader)?;
-        let mut data = vec![0u8; length.value as usize];
-        reader.read_exact(&mut data)?;
-        Ok(GenericMetaEvent { length, data })
+    pub fn import<R: Read>(reader: &mut R, meta_type: u8) -> Result<GenericMetaEvent> {
+        GenericMetaEvent::import_limited(reader, meta_type, DataLimits::default())
+    }
+    pub(crate) fn import_limited<R: Read>(
+        reader: &mut R,
+        meta_type: u8,
+        limits: DataLimits,
+    ) -> Result<GenericMetaEvent> {
+        let mut length = VLV::import(reader)?;
+        let data = read_data(reader, &mut length, limits)?;
+        Ok(GenericMetaEvent {
+            meta_type,
+            length,
+            data,
+        })
+    }
+    /// Replaces the data of this event, updating its length accordingly.
+    pub fn set_data(&mut self, data: Vec<u8>) -> Result<()> {
+        self.length = VLV::new(u32::try_from(data.len()).unwrap_or(u32::MAX))?;
+        self.data = data;
+        Ok(())
+    }
+    /// The length to write, always computed from the data in case it was edited directly. The padding of the original length is kept if any.
+    fn data_length(&self) -> Result<VLV> {
+        let value = u32::try_from(self.data.len()).unwrap_or(u32::MAX);
+        match self.length.padded_length() {
+            Some(width) if width >= calc_vlv_length(value)? => VLV::with_width(value, width),
+            _ => VLV::new(value),
+        }
+    }
+    pub fn export<W: Write>(&self, writer: &mut W) -> Result<()> {
+        self.export_as(writer, self.meta_type)
+    }
+    /// Writes the event with a given type, so that a Sequencer Specific event is always written as one
+    fn export_as<W: Write>(&self, writer: &mut W, meta_type: u8) -> Result<()> {
+        writer.write_all(&[0xFF, meta_type])?;
+        self.data_length()?.export(writer)?;
+        writer.write_all(&self.data)?;
+        Ok(())
+    }
+}
+
+/// Bounds on the data a SysEx or Meta Event can declare while it is being read
+#[derive(Copy, Clone, Default)]
+pub(crate) struct DataLimits {
+    /// Limit set by the user in the ImportOptions
+    pub max_event_data: Option<usize>,
+    /// Number of bytes left in the track at the start of the event
+    pub remaining: Option<u64>,
+    /// Discards the data instead of reading it, see ImportOptions
+    pub skip_payloads: bool,
+}
+
+/// Reads the data of a SysEx or Meta Event following its length, refusing to allocate more than the limits allow.
+/// When payloads are skipped, nothing is allocated: the data is discarded and the length set to 0 to match.
+fn read_data<R: Read>(reader: &mut R, length: &mut VLV, limits: DataLimits) -> Result<Vec<u8>> {
+    if let Some(max) = limits.max_event_data.filter(|_| !limits.skip_payloads) {
+        if length.value as usize > max {
+            return Err(SMFError::LimitExceeded(u64::from(length.value), max as u64));
+        }
+    }
+    if let Some(remaining) = limits.remaining {
+        if u64::from(length.value) > remaining {
+            return Err(SMFError::TruncatedEvent(length.value, remaining));
+        }
+    }
+    if limits.skip_payloads {
+        skip(reader, u64::from(length.value))?;
+        *length = VLV::new(0)?;
+        return Ok(Vec::new());
+    }
+    // Read through take, so nothing more than what is actually there gets allocated
+    let mut data = Vec::new();
+    reader
+        .take(u64::from(length.value))
+        .read_to_end(&mut data)?;
+    if data.len() < length.value as usize {
+        return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+    }
+    Ok(data)
+}
+
+/// Decodes the bytes of a text as UTF-8, also returning them if they had to be changed to do so.
+fn decode_text(data: Vec<u8>) -> (String, Option<Vec<u8>>) {
+    match String::from_utf8(data) {
+        Ok(text) => (text, None),
+        Err(e) => {
+            let data = e.into_bytes();
+            (String::from_utf8_lossy(&data).into_owned(), Some(data))
+        }
     }
 }
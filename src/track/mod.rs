@@ -1,37 +1,83 @@
+pub mod analysis;
+pub mod builder;
+mod edit;
 pub mod event;
 
-use crate::vlv::VLV;
+use crate::error::SMFError;
+use crate::options::ImportOptions;
+use crate::stream::{check_magic, CountingReader};
+use crate::vlv::{MAX_DELTA_TIME, VLV};
 use crate::Result;
-use event::Event;
+use event::{DataLimits, EndOfTrack, Event};
 use ez_io::{MagicNumberCheck, ReadE, WriteE};
-use std::io::{Read, Seek, SeekFrom, Write};
+use std::io::{Cursor, Read, Write};
 
 /// A MTrk Track inside a MIDI File. This contains TrackEvents containing a Delta Time and an Event.
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct SMFTrack {
     /// Size in bytes of this track
     pub length: u32,
     /// The Track Events contained inside this track
     pub track_events: Vec<TrackEvent>,
+    /// Offset in bytes of the start of each Track Event in the source file, only filled when importing with `track_offsets`.
+    /// It is not updated when the track is edited.
+    pub offsets: Vec<u64>,
 }
 
 impl SMFTrack {
     /// Reads a MTrk chunk from a mIDI File
-    pub fn import<R: Read + Seek>(reader: &mut R) -> Result<SMFTrack> {
-        reader.check_magic_number(&[b'M', b'T', b'r', b'k'])?;
+    pub fn import<R: Read>(reader: &mut R) -> Result<SMFTrack> {
+        SMFTrack::import_with_options(reader, &ImportOptions::default())
+    }
+
+    /// Reads a MTrk chunk from a MIDI File, with limits for untrusted input.
+    /// Offsets recorded with `track_offsets` are relative to the start of the track data, i.e. just after its length.
+    pub fn import_with_options<R: Read>(
+        reader: &mut R,
+        options: &ImportOptions,
+    ) -> Result<SMFTrack> {
+        check_magic(reader, b"MTrk", options.lenient_magic)?;
+        SMFTrack::import_after_magic(reader, options)
+    }
+
+    /// Reads a MTrk chunk whose magic number has already been checked
+    pub(crate) fn import_after_magic<R: Read>(
+        reader: &mut R,
+        options: &ImportOptions,
+    ) -> Result<SMFTrack> {
         let length = reader.read_be_to_u32()?;
-        // Number of bytes read in this track
-        let mut read_bytes = 0;
+        if let Some(max) = options.max_track_bytes {
+            if length > max {
+                return Err(SMFError::LimitExceeded(u64::from(length), u64::from(max)));
+            }
+        }
+        // Count the bytes read in this track, as we can't rely on Seek
+        let mut counter = CountingReader::new(reader);
         // All the track events in this track
         let mut track_events = Vec::new();
-        // Set the first offset for this track
-        let mut previous_location = reader.seek(SeekFrom::Current(0))?;
+        // Offsets of the track events, relative to the start of the track data
+        let mut offsets = Vec::new();
         // Previous code byte used for Running Status
         let mut previous_code_byte = None;
         // While there are still some bytes to read
-        while read_bytes < u64::from(length) {
+        while counter.count < u64::from(length) {
             // Read a track event
-            let answ = TrackEvent::import(reader, previous_code_byte)?;
+            let limits = DataLimits {
+                max_event_data: options.max_event_data,
+                remaining: Some(u64::from(length) - counter.count),
+                skip_payloads: options.skip_payloads,
+            };
+            let offset = counter.count;
+            let answ = match TrackEvent::import_limited(&mut counter, previous_code_byte, limits) {
+                Ok(answ) => answ,
+                Err(e) if options.track_offsets => {
+                    return Err(SMFError::AtOffset(offset, Box::new(e)))
+                }
+                Err(e) => return Err(e),
+            };
+            if options.track_offsets {
+                offsets.push(offset);
+            }
             // Extract the track event itself
             let track_event = answ.0;
             // Get the code byte of this event as well
@@ -39,31 +85,173 @@ impl SMFTrack {
             // Push the track event to the Vec
             track_events.push(track_event);
             // Update the previous code byte
-            previous_code_byte = Some(code_byte);
-            // Update where we are at in the track
-            let location_now = reader.seek(SeekFrom::Current(0))?;
-            // Add the amount of read bytes
-            read_bytes += location_now - previous_location;
-            // Set the previous location to where we are at right now
-            previous_location = location_now;
+            previous_code_byte = next_running_status(code_byte);
         }
         Ok(SMFTrack {
             length,
             track_events,
+            offsets,
+        })
+    }
+
+    /// Reads a MTrk chunk from a MIDI File without decoding its events, keeping its data as is.
+    /// This allows writing it back exactly, even with events this crate can't fully handle.
+    pub fn import_raw<R: Read>(reader: &mut R) -> Result<RawTrack> {
+        reader.check_magic_number(b"MTrk")?;
+        let length = reader.read_be_to_u32()?;
+        let mut data = Vec::new();
+        reader.take(u64::from(length)).read_to_end(&mut data)?;
+        if data.len() < length as usize {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
+        Ok(RawTrack { data })
+    }
+
+    /// Reads the Track Events of a MTrk chunk one at a time, from its data (everything after the length) of `length` bytes, without keeping them.
+    /// This allows going through huge tracks with little memory. Iteration stops after the first error.
+    pub fn stream_events<R: Read>(
+        reader: R,
+        length: u32,
+    ) -> impl Iterator<Item = Result<TrackEvent>> {
+        let mut counter = CountingReader::new(reader);
+        // Previous code byte used for Running Status
+        let mut previous_code_byte = None;
+        let mut failed = false;
+        std::iter::from_fn(move || {
+            if failed || counter.count >= u64::from(length) {
+                return None;
+            }
+            let limits = DataLimits {
+                remaining: Some(u64::from(length) - counter.count),
+                ..DataLimits::default()
+            };
+            match TrackEvent::import_limited(&mut counter, previous_code_byte, limits) {
+                Ok((track_event, code_byte)) => {
+                    previous_code_byte = next_running_status(code_byte);
+                    Some(Ok(track_event))
+                }
+                Err(e) => {
+                    failed = true;
+                    Some(Err(e))
+                }
+            }
+        })
+    }
+
+    /// Computes the size in bytes the events of this track take once written, without using Running Status.
+    /// A Delta-Time can't go beyond 2^28-1 ticks: such a gap is not split automatically, and DeltaTooLarge gives the index of the event after it.
+    pub fn computed_length(&self) -> Result<u32> {
+        let mut length = 0;
+        for (i, track_event) in self.track_events.iter().enumerate() {
+            if track_event.delta_time.value > MAX_DELTA_TIME {
+                return Err(SMFError::DeltaTooLarge(
+                    i,
+                    u64::from(track_event.delta_time.value),
+                ));
+            }
+            length += track_event.get_length()?;
+        }
+        Ok(length)
+    }
+
+    /// Brings the track back to a consistent state after it has been edited: makes sure it ends with an EndOfTrack and updates the length.
+    pub fn normalize(&mut self) -> Result<()> {
+        self.ensure_end_of_track()?;
+        self.length = self.computed_length()?;
+        Ok(())
+    }
+
+    /// Calls a closure on every event of this track, allowing to modify them.
+    /// As this can change the size of the events, `length` is outdated until `normalize` is called.
+    pub fn for_each_event_mut<F: FnMut(&mut Event)>(&mut self, mut f: F) {
+        for track_event in &mut self.track_events {
+            f(&mut track_event.event);
+        }
+    }
+
+    /// Checks that the last event of this track is an EndOfTrack, as required by the spec.
+    pub fn ends_properly(&self) -> bool {
+        match self.track_events.last() {
+            Some(track_event) => matches!(track_event.event, Event::EndOfTrack(_)),
+            None => false,
+        }
+    }
+
+    /// Makes sure this track ends with one and only one EndOfTrack.
+    /// An EndOfTrack found before the end is removed, its Delta-Time being added to the next event so timing is kept.
+    /// If the track does not end with an EndOfTrack, one is appended with a Delta-Time of 0.
+    /// Fails with DeltaTooLarge, leaving the track unchanged, if an event would end up too far from the previous one, see `computed_length`.
+    pub fn ensure_end_of_track(&mut self) -> Result<()> {
+        let last = self.track_events.len().saturating_sub(1);
+        // Delta-Time of the removed events, to carry over to the next one
+        let mut carry = 0u64;
+        let mut track_events = Vec::with_capacity(self.track_events.len() + 1);
+        for (i, track_event) in self.track_events.iter().enumerate() {
+            let delta_time = u64::from(track_event.delta_time.value) + carry;
+            if let Event::EndOfTrack(_) = track_event.event {
+                if i != last {
+                    carry = delta_time;
+                    continue;
+                }
+            }
+            if delta_time > u64::from(MAX_DELTA_TIME) {
+                return Err(SMFError::DeltaTooLarge(i, delta_time));
+            }
+            let mut track_event = track_event.clone();
+            track_event.delta_time.value = delta_time as u32;
+            carry = 0;
+            track_events.push(track_event);
+        }
+        self.track_events = track_events;
+        if !self.ends_properly() {
+            self.track_events.push(TrackEvent {
+                delta_time: VLV::new(0)?,
+                event: Event::EndOfTrack(EndOfTrack {}),
+            });
+        }
+        Ok(())
+    }
+
+    /// Iterates over the events of this track along with their absolute time in ticks, i.e. the sum of all Delta-Times up to them.
+    pub fn iter_absolute(&self) -> impl Iterator<Item = (u64, &Event)> {
+        self.track_events.iter().scan(0u64, |time, track_event| {
+            *time += u64::from(track_event.delta_time.value);
+            Some((*time, &track_event.event))
         })
     }
 
-    /// Writes a MTrk chunk to a MIDI File
+    /// Returns the time in ticks of the last event of this track, EndOfTrack included, i.e. the sum of all Delta-Times.
+    pub fn duration_ticks(&self) -> u64 {
+        self.track_events
+            .iter()
+            .map(|track_event| u64::from(track_event.delta_time.value))
+            .sum()
+    }
+
+    /// Writes a MTrk chunk to a MIDI File. Events are written without Running Status, so the length written is computed from them
+    /// rather than taken from `length`, which may come from a file using Running Status or be outdated after an edit.
     pub fn export<W: Write>(&self, writer: &mut W) -> Result<()> {
-        writer.write_all(&[b'M', b'T', b'r', b'k'])?;
-        writer.write_be_to_u32(self.length)?;
-        unimplemented!();
-        // Ok(())
+        let computed_length = self.computed_length()?;
+        writer.write_all(b"MTrk")?;
+        writer.write_be_to_u32(computed_length)?;
+        for track_event in &self.track_events {
+            track_event.export(writer)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the code byte that following events can reuse through Running Status. SysEx and Meta Events cancel it.
+fn next_running_status(code_byte: u8) -> Option<u8> {
+    if code_byte < 0xF0 {
+        Some(code_byte)
+    } else {
+        None
     }
 }
 
 /// The data inside of a MIDI Track.
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct TrackEvent {
     /// A Delta-Time indicates how much time this event should take place after the previous one, in ticks.
     pub delta_time: VLV,
@@ -76,13 +264,40 @@ impl TrackEvent {
     pub fn get_length(&self) -> Result<u32> {
         Ok(u32::from(self.delta_time.get_length()?) + self.event.get_length()?)
     }
+    /// Returns the status byte of the event if it is a MIDI Channel Event, i.e. the byte that Running Status can omit.
+    pub fn status_byte(&self) -> Option<u8> {
+        self.event.status_byte()
+    }
     /// Reads the Delta Time and the associated event.
-    pub fn import<R: Read + Seek>(
+    pub fn import<R: Read>(
+        reader: &mut R,
+        previous_code_byte: Option<u8>,
+    ) -> Result<(TrackEvent, u8)> {
+        TrackEvent::import_with_options(reader, previous_code_byte, &ImportOptions::default())
+    }
+
+    /// Reads the Delta Time and the associated event, with limits on the data of SysEx and Meta Events.
+    pub fn import_with_options<R: Read>(
         reader: &mut R,
         previous_code_byte: Option<u8>,
+        options: &ImportOptions,
+    ) -> Result<(TrackEvent, u8)> {
+        let limits = DataLimits {
+            max_event_data: options.max_event_data,
+            remaining: None,
+            skip_payloads: options.skip_payloads,
+        };
+        TrackEvent::import_limited(reader, previous_code_byte, limits)
+    }
+
+    /// Reads the Delta Time and the associated event, checking the sizes declared by SysEx and Meta Events
+    pub(crate) fn import_limited<R: Read>(
+        reader: &mut R,
+        previous_code_byte: Option<u8>,
+        limits: DataLimits,
     ) -> Result<(TrackEvent, u8)> {
         let delta_time = VLV::import(reader)?;
-        let stuff = Event::import(reader, previous_code_byte)?;
+        let stuff = Event::import_limited(reader, previous_code_byte, limits)?;
         let event = stuff.0;
         let code_byte = stuff.1;
         Ok((TrackEvent { delta_time, event }, code_byte))
@@ -90,6 +305,105 @@ impl TrackEvent {
 
     /// Writes the Track Event.
     pub fn export<W: Write>(&self, writer: &mut W) -> Result<()> {
-        unimplemented!();
+        self.delta_time.export(writer)?;
+        self.event.export(writer)
+    }
+}
+
+/// A MTrk Track whose events have not been decoded, see `SMFTrack::import_raw`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RawTrack {
+    /// The data of the track, everything after its length
+    pub data: Vec<u8>,
+}
+
+impl RawTrack {
+    /// Decodes the events of this track
+    pub fn parse(&self) -> Result<SMFTrack> {
+        let length = (self.data.len() as u32).to_be_bytes();
+        SMFTrack::import(&mut (&b"MTrk"[..]).chain(&length[..]).chain(&self.data[..]))
+    }
+
+    /// Iterates over the events of this track as they were stored, without decoding them all at once
+    pub fn stored_events(&self) -> StoredTrackEvents<'_> {
+        StoredTrackEvents::new(&self.data)
+    }
+
+    /// Writes the MTrk chunk back, exactly as it was read
+    pub fn export<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_all(b"MTrk")?;
+        writer.write_be_to_u32(self.data.len() as u32)?;
+        writer.write_all(&self.data)?;
+        Ok(())
+    }
+}
+
+/// A Track Event along with information on how it was stored in a MIDI File.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StoredTrackEvent {
+    /// Offset in bytes of the start of the Track Event (its Delta-Time), relative to the start of the track data
+    pub offset: u64,
+    /// True if the status byte was omitted on disk thanks to Running Status
+    pub running_status: bool,
+    /// The decoded Track Event
+    pub track_event: TrackEvent,
+}
+
+/// Iterator reading the Track Events one by one from the data of a MTrk chunk (everything after the length), borrowing it instead of copying it.
+pub struct StoredTrackEvents<'a> {
+    /// Reader over the track data
+    cursor: Cursor<&'a [u8]>,
+    /// Previous code byte used for Running Status
+    previous_code_byte: Option<u8>,
+    /// Set after an error to stop iterating
+    failed: bool,
+}
+
+impl<'a> StoredTrackEvents<'a> {
+    /// Starts reading events at the beginning of the track data
+    pub fn new(data: &'a [u8]) -> StoredTrackEvents<'a> {
+        StoredTrackEvents {
+            cursor: Cursor::new(data),
+            previous_code_byte: None,
+            failed: false,
+        }
+    }
+}
+
+impl<'a> Iterator for StoredTrackEvents<'a> {
+    type Item = Result<StoredTrackEvent>;
+
+    fn next(&mut self) -> Option<Result<StoredTrackEvent>> {
+        let data = *self.cursor.get_ref();
+        if self.failed || self.cursor.position() >= data.len() as u64 {
+            return None;
+        }
+        let offset = self.cursor.position();
+        let delta_time = match VLV::import(&mut self.cursor) {
+            Ok(d) => d,
+            Err(e) => {
+                self.failed = true;
+                return Some(Err(e));
+            }
+        };
+        // A data byte where a status byte is expected means Running Status
+        let running_status = match data.get(self.cursor.position() as usize) {
+            Some(b) => b & 0b1000_0000u8 == 0,
+            None => false,
+        };
+        match Event::import(&mut self.cursor, self.previous_code_byte) {
+            Ok((event, code_byte)) => {
+                self.previous_code_byte = next_running_status(code_byte);
+                Some(Ok(StoredTrackEvent {
+                    offset,
+                    running_status,
+                    track_event: TrackEvent { delta_time, event },
+                }))
+            }
+            Err(e) => {
+                self.failed = true;
+                Some(Err(e))
+            }
+        }
     }
 }
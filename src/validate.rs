@@ -0,0 +1,94 @@
+use crate::header::Format;
+use crate::track::event::Event;
+use crate::track::SMFTrack;
+use crate::SMF;
+
+/// A problem found in a MIDI File by `SMF::validate`. Such files can be read and written, but may not play as intended.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ValidationIssue {
+    /// The Format declared in the header does not match the tracks, see `SMF::suggested_format`
+    FormatMismatch { declared: Format, suggested: Format },
+    /// A SysEx message starting with a F0 packet is never terminated by a F7 byte, leaving the receiving device waiting for the rest
+    UnterminatedSysEx { track: usize, tick: u64 },
+    /// A Sequence Number found after the start of the track: it must come before any non-zero Delta-Time and any MIDI or SysEx event
+    MisplacedSequenceNumber { track: usize, tick: u64 },
+}
+
+impl SMF {
+    /// Looks for problems in this MIDI File: first in the header, then in each track in order.
+    pub fn validate(&self) -> Vec<ValidationIssue> {
+        let mut issues = Vec::new();
+        let suggested = self.suggested_format();
+        if suggested != self.header.format {
+            issues.push(ValidationIssue::FormatMismatch {
+                declared: self.header.format,
+                suggested,
+            });
+        }
+        for (index, track) in self.tracks.iter().enumerate() {
+            check_sysex(index, track, &mut issues);
+            check_sequence_number(index, track, &mut issues);
+        }
+        issues
+    }
+
+    /// Gives the Format this file should declare given its tracks: SingleTrack for a single track, MultipleTrack for several tracks played together.
+    /// A file declaring MultipleSong with several tracks keeps it, as independent songs can't be told apart from tracks of the same song.
+    pub fn suggested_format(&self) -> Format {
+        match (self.tracks.len(), self.header.format) {
+            (0, format) => format,
+            (1, _) => Format::SingleTrack,
+            (_, Format::MultipleSong) => Format::MultipleSong,
+            _ => Format::MultipleTrack,
+        }
+    }
+}
+
+/// Checks that every SysEx message split into packets ends, before the end of the track or the start of another message
+fn check_sysex(index: usize, track: &SMFTrack, issues: &mut Vec<ValidationIssue>) {
+    // Start tick of the message waiting for its last packet
+    let mut pending = None;
+    for (tick, event) in track.iter_absolute() {
+        match event {
+            Event::SystemExclusiveF0(s) => {
+                if let Some(start) = pending {
+                    issues.push(ValidationIssue::UnterminatedSysEx {
+                        track: index,
+                        tick: start,
+                    });
+                }
+                pending = match s.data.last() {
+                    Some(0xF7) => None,
+                    _ => Some(tick),
+                };
+            }
+            // Outside of a message, F7 events are escapes and end nothing
+            Event::SystemExclusiveF7(s) if s.data.last() == Some(&0xF7) => pending = None,
+            _ => {}
+        }
+    }
+    if let Some(start) = pending {
+        issues.push(ValidationIssue::UnterminatedSysEx {
+            track: index,
+            tick: start,
+        });
+    }
+}
+
+/// Checks that Sequence Numbers are only found at the start of the track, where only other Meta Events can precede them
+fn check_sequence_number(index: usize, track: &SMFTrack, issues: &mut Vec<ValidationIssue>) {
+    let mut at_start = true;
+    for (tick, event) in track.iter_absolute() {
+        if tick > 0 {
+            at_start = false;
+        }
+        match event {
+            Event::SequenceNumber(_) if !at_start => {
+                issues.push(ValidationIssue::MisplacedSequenceNumber { track: index, tick })
+            }
+            Event::SystemExclusiveF0(_) | Event::SystemExclusiveF7(_) => at_start = false,
+            e if e.status_byte().is_some() => at_start = false,
+            _ => {}
+        }
+    }
+}
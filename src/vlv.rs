@@ -3,6 +3,9 @@ use crate::Result;
 use ez_io::{ReadE, WriteE};
 use std::io::{Read, Write};
 
+/// Biggest value a VLV, and so a Delta-Time, can hold: 2^28-1
+pub const MAX_DELTA_TIME: u32 = 0x0FFF_FFFF;
+
 /// Calculates the encoded length of a VLV, or throws an error when the number is too big to fit
 pub fn calc_vlv_length(value: u32) -> Result<u8> {
     Ok(if value < 2u32.pow(7) {
@@ -20,22 +23,67 @@ pub fn calc_vlv_length(value: u32) -> Result<u8> {
 
 /// Represents a Variable Length Value. This is format that represents a number. The particularity of VLVs is that depending on the represented number, the VLV takes more or less space, from 1 to 4 bytes.
 /// The minimum value is 0 and the maximum is 2^28-1.
-#[derive(Copy, Clone)]
+/// Two VLVs are equal if they represent the same value, whatever their encoded length.
+#[derive(Copy, Clone, Debug)]
 pub struct VLV {
     /// The value represented by this VLV
     pub value: u32,
+    /// When a VLV was read using more bytes than needed (e.g. 0 as 0x80 0x00), the length it had, so it can be written back the same way
+    padded_length: Option<u8>,
+}
+
+impl PartialEq for VLV {
+    fn eq(&self, other: &VLV) -> bool {
+        self.value == other.value
+    }
 }
 
 impl VLV {
-    /// Creates a new VLV from a user provided value
+    /// Creates a new VLV from a user provided value, that will be written using as few bytes as possible
     pub fn new(value: u32) -> Result<VLV> {
         let _ = calc_vlv_length(value)?;
-        Ok(VLV { value })
+        Ok(VLV {
+            value,
+            padded_length: None,
+        })
+    }
+
+    /// Creates a new VLV that will be written using `width` bytes, padding it if needed (e.g. 0 as 0x80 0x00).
+    /// Fails if `width` is more than 4 bytes or too small to hold the value.
+    pub fn with_width(value: u32, width: u8) -> Result<VLV> {
+        if width > 4 {
+            return Err(SMFError::VLV(VLVError::VLVTooBig));
+        }
+        if width < calc_vlv_length(value)? {
+            return Err(SMFError::VLV(VLVError::NumberTooBig(value)));
+        }
+        Ok(VLV::from_read(value, width))
+    }
+
+    /// The length this VLV is padded to when written, if it uses more bytes than needed
+    pub fn padded_length(self) -> Option<u8> {
+        self.padded_length
+    }
+
+    /// Creates a VLV from a value and the number of bytes it was read with, remembering it if it was not minimal
+    fn from_read(value: u32, read_length: u8) -> VLV {
+        let padded_length = match calc_vlv_length(value) {
+            Ok(l) if l < read_length => Some(read_length),
+            _ => None,
+        };
+        VLV {
+            value,
+            padded_length,
+        }
     }
 
     /// Returns the length in bytes of the encoded VLV
     pub fn get_length(self) -> Result<u8> {
-        calc_vlv_length(self.value)
+        let minimal_length = calc_vlv_length(self.value)?;
+        Ok(match self.padded_length {
+            Some(l) if l > minimal_length && l <= 4 => l,
+            _ => minimal_length,
+        })
     }
 
     /// Read a VLV from a file
@@ -54,7 +102,7 @@ impl VLV {
             value <<= 7;
             value |= u32::from(code_byte & 0b0111_1111);
             if (code_byte & 0b1000_0000u8) == 0 {
-                return Ok(VLV { value });
+                return Ok(VLV::from_read(value, real_length));
             }
         }
     }
@@ -76,7 +124,7 @@ impl VLV {
             value <<= 7;
             value |= u32::from(code_byte & 0b0111_1111);
             if (code_byte & 0b1000_0000u8) == 0 {
-                return Ok(VLV { value });
+                return Ok(VLV::from_read(value, real_length));
             }
             code_byte = reader.read_to_u8()?;
         }
@@ -84,8 +132,8 @@ impl VLV {
 
     /// Writes a VLV to a file
     pub fn export<W: Write>(self, writer: &mut W) -> Result<()> {
-        // Calc real length
-        let real_length = calc_vlv_length(self.value)?;
+        // Calc real length, keeping the padding of the original VLV if any
+        let real_length = self.get_length()?;
 
         // For each byte we need to write
         for idx in 0..real_length {
@@ -0,0 +1,448 @@
+mod common;
+
+use common::*;
+use standard_midi_file::analysis::{SongClass, TextEncoding};
+use standard_midi_file::header::{Format, TimeScale};
+use standard_midi_file::track::analysis::{Note, VelocityStats};
+use standard_midi_file::track::event::{
+    ControllerChange, Event, Key, KeySignature, MIDIPort, TextMessage, TimeSignature,
+};
+
+#[test]
+fn stuck_notes_dangling() {
+    let track = track(vec![
+        (0, note_on(0, 60, 100)),
+        (0, note_on(0, 64, 100)),
+        (96, note_off(0, 60)),
+        (0, end_of_track()),
+    ]);
+    assert_eq!(track.stuck_notes(), vec![(0, 0, 64)]);
+}
+
+#[test]
+fn stuck_notes_double_on() {
+    let track = track(vec![
+        (0, note_on(1, 60, 100)),
+        (48, note_on(1, 60, 100)),
+        (48, note_on(1, 60, 0)),
+        (0, end_of_track()),
+    ]);
+    assert_eq!(track.stuck_notes(), vec![(0, 1, 60)]);
+}
+
+#[test]
+fn stuck_notes_none() {
+    let track = track(vec![
+        (0, note_on(0, 60, 100)),
+        (96, note_off(0, 60)),
+        (0, end_of_track()),
+    ]);
+    assert!(track.stuck_notes().is_empty());
+}
+
+#[test]
+fn note_balance() {
+    let track = track(vec![
+        (0, note_on(0, 60, 100)),
+        (0, note_on(2, 64, 100)),
+        (0, note_on(2, 67, 100)),
+        (96, note_on(0, 60, 0)),
+        (0, note_off(2, 64)),
+        (0, end_of_track()),
+    ]);
+    let mut expected = [0; 16];
+    expected[2] = 1;
+    assert_eq!(track.note_balance(), expected);
+}
+
+#[test]
+fn velocity_stats() {
+    let track = track(vec![
+        (0, note_on(0, 60, 40)),
+        (0, note_on(0, 64, 80)),
+        (0, note_on(0, 67, 120)),
+        (96, note_on(0, 60, 0)),
+        (0, note_off(0, 64)),
+        (0, note_off(0, 67)),
+        (0, end_of_track()),
+    ]);
+    assert_eq!(
+        track.velocity_stats(),
+        Some(VelocityStats {
+            min: 40,
+            max: 120,
+            mean: 80.0
+        })
+    );
+    let empty = common::track(vec![(0, end_of_track())]);
+    assert_eq!(empty.velocity_stats(), None);
+}
+
+#[test]
+fn markers() {
+    let smf = smf(
+        Format::MultipleTrack,
+        vec![
+            track(vec![
+                (0, Event::Marker(text_message("Intro"))),
+                (384, Event::CuePoint(text_message("Door slams"))),
+                (384, Event::Marker(text_message("Verse"))),
+                (0, end_of_track()),
+            ]),
+            track(vec![
+                (0, note_on(0, 60, 100)),
+                (96, note_off(0, 60)),
+                (0, end_of_track()),
+            ]),
+        ],
+    );
+    assert_eq!(
+        smf.markers(false),
+        vec![(0, "Intro".to_string()), (768, "Verse".to_string())]
+    );
+    assert_eq!(smf.markers(true)[1], (384, "Door slams".to_string()));
+}
+
+#[test]
+fn primary_key_signature() {
+    let g_major = KeySignature {
+        flats_sharps: 1,
+        key: Key::Major,
+    };
+    let e_minor = KeySignature {
+        flats_sharps: 1,
+        key: Key::Minor,
+    };
+    let mut smf = smf(
+        Format::MultipleTrack,
+        vec![
+            track(vec![(0, Event::KeySignature(g_major)), (0, end_of_track())]),
+            track(vec![
+                (0, note_on(0, 67, 100)),
+                (384, note_off(0, 67)),
+                (0, end_of_track()),
+            ]),
+        ],
+    );
+    assert_eq!(smf.primary_key_signature(), Some(g_major));
+    // A short passage in another key does not change it
+    smf.tracks[0] = track(vec![
+        (0, Event::KeySignature(g_major)),
+        (288, Event::KeySignature(e_minor)),
+        (0, end_of_track()),
+    ]);
+    assert_eq!(smf.primary_key_signature(), Some(g_major));
+    smf.tracks.remove(0);
+    assert_eq!(smf.primary_key_signature(), None);
+}
+
+#[test]
+fn piano_roll() {
+    let track = track(vec![
+        (0, note_on(0, 60, 100)),
+        (48, note_off(0, 60)),
+        (0, note_on(0, 64, 100)),
+        (60, note_off(0, 64)),
+        (84, end_of_track()),
+    ]);
+    let roll = track.piano_roll(24);
+    assert_eq!(roll.len(), 128);
+    assert_eq!(
+        roll[60],
+        vec![true, true, false, false, false, false, false, false]
+    );
+    assert_eq!(
+        roll[64],
+        vec![false, false, true, true, true, false, false, false]
+    );
+    assert!(roll[62].iter().all(|lit| !lit));
+}
+
+#[test]
+fn ports() {
+    let smf = smf(
+        Format::MultipleTrack,
+        vec![
+            track(vec![
+                (0, Event::DeviceName(text_message("Synth A"))),
+                (0, Event::MIDIPort(MIDIPort { port: 0 })),
+                (0, note_on(0, 60, 100)),
+                (96, note_off(0, 60)),
+                (0, end_of_track()),
+            ]),
+            track(vec![
+                (0, Event::MIDIPort(MIDIPort { port: 1 })),
+                (0, end_of_track()),
+            ]),
+        ],
+    );
+    let ports = smf.ports();
+    assert_eq!(ports.len(), 2);
+    assert_eq!(ports[&0], Some("Synth A".to_string()));
+    assert_eq!(ports[&1], None);
+}
+
+#[test]
+fn drum_sounds() {
+    let smf = smf(
+        Format::SingleTrack,
+        vec![track(vec![
+            (0, note_on(9, 38, 100)),
+            (0, note_on(9, 36, 100)),
+            (0, note_on(0, 60, 100)),
+            (48, note_off(9, 36)),
+            (0, note_off(9, 38)),
+            (0, note_on(9, 36, 100)),
+            (48, note_off(9, 36)),
+            (0, note_off(0, 60)),
+            (0, end_of_track()),
+        ])],
+    );
+    let sounds = smf.drum_sounds();
+    assert_eq!(sounds.len(), 2);
+    assert!(sounds.contains(&36) && sounds.contains(&38));
+    assert_eq!(
+        smf.drum_sound_names(),
+        vec!["Bass Drum 1", "Acoustic Snare"]
+    );
+}
+
+#[test]
+fn note_density() {
+    let track = track(vec![
+        (0, note_on(0, 60, 100)),
+        (0, note_on(0, 64, 100)),
+        (24, note_on(0, 67, 100)),
+        (72, note_off(0, 60)),
+        (0, note_off(0, 64)),
+        (0, note_off(0, 67)),
+        (96, note_on(0, 72, 100)),
+        (96, note_off(0, 72)),
+        (0, end_of_track()),
+    ]);
+    assert_eq!(track.note_density(96), vec![(0, 3), (96, 0), (192, 1)]);
+    assert!(track.note_density(0).is_empty());
+}
+
+fn single_note(channel: u8) -> standard_midi_file::track::SMFTrack {
+    track(vec![
+        (0, note_on(channel, 40, 100)),
+        (96, note_off(channel, 40)),
+        (0, end_of_track()),
+    ])
+}
+
+#[test]
+fn classify_drums_only() {
+    let smf = smf(Format::MultipleTrack, vec![single_note(9), single_note(9)]);
+    assert_eq!(smf.classify(), SongClass::DrumsOnly);
+}
+
+#[test]
+fn classify_melodic() {
+    let smf = smf(Format::MultipleTrack, vec![single_note(0), single_note(3)]);
+    assert_eq!(smf.classify(), SongClass::Melodic);
+}
+
+#[test]
+fn classify_mixed() {
+    let smf = smf(Format::MultipleTrack, vec![single_note(0), single_note(9)]);
+    assert_eq!(smf.classify(), SongClass::Mixed);
+}
+
+#[test]
+fn legato_notes() {
+    let track = track(vec![
+        (0, note_on(0, 60, 100)),
+        (96, note_off(0, 60)),
+        // Tied C
+        (0, note_on(0, 60, 80)),
+        (0, note_on(0, 64, 90)),
+        (96, note_off(0, 60)),
+        (0, note_off(0, 64)),
+        // Not tied, there is a gap
+        (48, note_on(0, 64, 90)),
+        (48, note_off(0, 64)),
+        (0, end_of_track()),
+    ]);
+    let note = |key, velocity, start, end| Note {
+        channel: 0,
+        key,
+        velocity,
+        start,
+        end,
+    };
+    assert_eq!(
+        track.legato_notes(),
+        vec![
+            note(60, 100, 0, 192),
+            note(64, 90, 96, 192),
+            note(64, 90, 240, 288)
+        ]
+    );
+}
+
+#[test]
+fn static_tempo() {
+    let with_tempos = |tempos: Vec<(u32, u32)>| {
+        let mut events: Vec<(u32, Event)> =
+            tempos.into_iter().map(|(d, t)| (d, tempo(t))).collect();
+        events.push((0, end_of_track()));
+        smf(Format::SingleTrack, vec![track(events)])
+    };
+    assert_eq!(with_tempos(vec![]).static_tempo(), Some(500_000));
+    assert_eq!(
+        with_tempos(vec![(0, 400_000)]).static_tempo(),
+        Some(400_000)
+    );
+    assert_eq!(
+        with_tempos(vec![(0, 400_000), (96, 400_000)]).static_tempo(),
+        Some(400_000)
+    );
+    assert_eq!(
+        with_tempos(vec![(0, 400_000), (96, 600_000)]).static_tempo(),
+        None
+    );
+    // Starts at the default tempo
+    assert_eq!(with_tempos(vec![(96, 400_000)]).static_tempo(), None);
+}
+
+#[test]
+fn controllers_per_channel() {
+    let controller = |channel, controller_number, value| {
+        Event::ControllerChange(ControllerChange {
+            channel,
+            controller_number,
+            value,
+        })
+    };
+    let smf = smf(
+        Format::MultipleTrack,
+        vec![
+            track(vec![
+                (0, controller(0, 1, 20)),
+                (0, controller(0, 7, 100)),
+                (96, controller(0, 1, 40)),
+                (0, end_of_track()),
+            ]),
+            track(vec![(0, controller(3, 7, 90)), (0, end_of_track())]),
+        ],
+    );
+    let controllers = smf.controllers_per_channel();
+    assert_eq!(controllers[0], [1, 7].iter().copied().collect());
+    assert_eq!(controllers[3], [7].iter().copied().collect());
+    assert!(controllers[1].is_empty());
+}
+
+#[test]
+fn used_channels() {
+    let smf = smf(
+        Format::MultipleTrack,
+        vec![
+            track(vec![
+                (0, Event::Marker(text_message("Intro"))),
+                (0, end_of_track()),
+            ]),
+            track(vec![
+                (0, note_on(0, 60, 100)),
+                (0, note_on(1, 64, 100)),
+                (96, note_off(0, 60)),
+                (0, note_off(1, 64)),
+                (0, end_of_track()),
+            ]),
+            track(vec![
+                (0, note_on(9, 36, 100)),
+                (96, note_off(9, 36)),
+                (0, end_of_track()),
+            ]),
+        ],
+    );
+    let per_track = smf.used_channels_per_track();
+    assert_eq!(per_track.len(), 3);
+    assert!(per_track[0].is_empty());
+    assert_eq!(per_track[1], [0, 1].iter().copied().collect());
+    assert_eq!(per_track[2], [9].iter().copied().collect());
+    assert_eq!(smf.used_channels(), [0, 1, 9].iter().copied().collect());
+}
+
+#[test]
+fn estimate_key() {
+    // A C major scale going up, then down to C through the chord of G
+    let melody = [60, 62, 64, 65, 67, 69, 71, 72, 67, 71, 74, 67, 64, 60];
+    let mut events = Vec::new();
+    for key in melody.iter() {
+        events.push((0, note_on(0, *key, 100)));
+        events.push((96, note_off(0, *key)));
+    }
+    // Drums don't count
+    events.push((0, note_on(9, 42, 100)));
+    events.push((0, note_on(9, 42, 0)));
+    events.push((0, end_of_track()));
+    let smf = smf(Format::SingleTrack, vec![track(events)]);
+    assert_eq!(
+        smf.estimate_key(),
+        Some(KeySignature {
+            flats_sharps: 0,
+            key: Key::Major
+        })
+    );
+    let silent = common::smf(Format::SingleTrack, vec![track(vec![(0, end_of_track())])]);
+    assert_eq!(silent.estimate_key(), None);
+}
+
+#[test]
+fn measure_count() {
+    let four_four = Event::TimeSignature(TimeSignature::new(4, 2, 24, 8));
+    let three_four = Event::TimeSignature(TimeSignature::new(3, 2, 24, 8));
+    // 8 quarter notes and a half in 4/4
+    let mut smf = smf(
+        Format::SingleTrack,
+        vec![track(vec![(0, four_four.clone()), (816, end_of_track())])],
+    );
+    assert_eq!(smf.measure_count(), Some(3));
+    // Two measures of 4/4, then three of 3/4
+    smf.tracks[0] = track(vec![
+        (0, four_four),
+        (768, three_four),
+        (864, end_of_track()),
+    ]);
+    assert_eq!(smf.measure_count(), Some(5));
+    smf.header.time_division = TimeScale::SMPTECompatible(-25, 40);
+    assert_eq!(smf.measure_count(), None);
+}
+
+#[test]
+fn guess_text_encoding() {
+    let texts = |texts: &[&[u8]]| {
+        let mut events: Vec<(u32, Event)> = texts
+            .iter()
+            .map(|t| {
+                (
+                    0,
+                    Event::Lyric(TextMessage::from_bytes(t.to_vec()).unwrap()),
+                )
+            })
+            .collect();
+        events.push((0, end_of_track()));
+        smf(Format::SingleTrack, vec![track(events)])
+    };
+    assert_eq!(
+        texts(&[b"Hello", b"World"]).guess_text_encoding(),
+        TextEncoding::Utf8
+    );
+    assert_eq!(
+        texts(&["Café".as_bytes()]).guess_text_encoding(),
+        TextEncoding::Utf8
+    );
+    // "Konnichiwa" in Shift-JIS, along with ASCII text
+    let konnichiwa: &[u8] = &[0x82, 0xB1, 0x82, 0xF1, 0x82, 0xC9, 0x82, 0xBF, 0x82, 0xCD];
+    assert_eq!(
+        texts(&[b"Title", konnichiwa]).guess_text_encoding(),
+        TextEncoding::ShiftJis
+    );
+    // "Cafe" with an acute accent in Latin-1
+    assert_eq!(
+        texts(&[&[0x43, 0x61, 0x66, 0xE9]]).guess_text_encoding(),
+        TextEncoding::Latin1
+    );
+}
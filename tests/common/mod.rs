@@ -0,0 +1,74 @@
+//! Helpers to build MIDI Files in tests
+#![allow(dead_code)]
+
+use standard_midi_file::header::*;
+use standard_midi_file::track::event::*;
+use standard_midi_file::track::*;
+use standard_midi_file::vlv::VLV;
+use standard_midi_file::SMF;
+
+/// Builds a track from Delta-Times and events, with a correct length
+pub fn track(events: Vec<(u32, Event)>) -> SMFTrack {
+    let mut track = SMFTrack {
+        length: 0,
+        track_events: events
+            .into_iter()
+            .map(|(delta_time, event)| TrackEvent {
+                delta_time: VLV::new(delta_time).unwrap(),
+                event,
+            })
+            .collect(),
+        offsets: Vec::new(),
+    };
+    track.length = track.computed_length().unwrap();
+    track
+}
+
+/// Builds a MIDI File with 96 ticks per quarter note
+pub fn smf(format: Format, tracks: Vec<SMFTrack>) -> SMF {
+    SMF {
+        header: SMFHeader {
+            length: 6,
+            format,
+            nb_tracks: tracks.len() as u16,
+            time_division: TimeScale::TicksPerQuarterNote(96),
+            extra: Vec::new(),
+        },
+        tracks,
+        trailing_chunks: Vec::new(),
+        warnings: Vec::new(),
+    }
+}
+
+/// Absolute times of every event in a track
+pub fn ticks(track: &SMFTrack) -> Vec<u64> {
+    track.iter_absolute().map(|(tick, _)| tick).collect()
+}
+
+pub fn note_on(channel: u8, key: u8, velocity: u8) -> Event {
+    Event::NoteOn(NoteChange {
+        channel,
+        key,
+        velocity,
+    })
+}
+
+pub fn note_off(channel: u8, key: u8) -> Event {
+    Event::NoteOff(NoteChange {
+        channel,
+        key,
+        velocity: 64,
+    })
+}
+
+pub fn tempo(value: u32) -> Event {
+    Event::Tempo(Tempo { value })
+}
+
+pub fn end_of_track() -> Event {
+    Event::EndOfTrack(EndOfTrack {})
+}
+
+pub fn text_message(text: &str) -> TextMessage {
+    TextMessage::new(text).unwrap()
+}
@@ -0,0 +1,34 @@
+use standard_midi_file::detect::*;
+use std::io::Cursor;
+
+#[test]
+fn smf() {
+    let mut reader = Cursor::new(vec![b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 0, 0, 1, 0, 96]);
+    assert_eq!(detect_format(&mut reader).unwrap(), DetectedFormat::Smf);
+    assert_eq!(reader.position(), 0);
+}
+
+#[test]
+fn rmid() {
+    let mut data = b"RIFF".to_vec();
+    data.extend_from_slice(&[22, 0, 0, 0]);
+    data.extend_from_slice(b"RMIDdata");
+    let mut reader = Cursor::new(data);
+    assert_eq!(detect_format(&mut reader).unwrap(), DetectedFormat::Rmid);
+    assert_eq!(reader.position(), 0);
+}
+
+#[test]
+fn unknown() {
+    let mut wave = b"RIFF".to_vec();
+    wave.extend_from_slice(&[22, 0, 0, 0]);
+    wave.extend_from_slice(b"WAVEfmt ");
+    assert_eq!(
+        detect_format(&mut Cursor::new(wave)).unwrap(),
+        DetectedFormat::Unknown
+    );
+    assert_eq!(
+        detect_format(&mut Cursor::new(b"MT".to_vec())).unwrap(),
+        DetectedFormat::Unknown
+    );
+}
@@ -0,0 +1,657 @@
+mod common;
+
+use common::*;
+use standard_midi_file::error::SMFError;
+use standard_midi_file::header::{Format, TimeScale};
+use standard_midi_file::track::event::{
+    ControllerChange, Event, MIDIChannelPrefix, ProgramChange, Tempo, TextMessage, TimeSignature,
+};
+
+fn instrument_name(text: &str) -> Event {
+    Event::InstrumentName(TextMessage::new(text).unwrap())
+}
+
+fn channel_prefix(channel: u8) -> Event {
+    Event::MIDIChannelPrefix(MIDIChannelPrefix { channel })
+}
+
+#[test]
+fn merge_tracks() {
+    let mut smf = smf(
+        Format::MultipleTrack,
+        vec![
+            track(vec![(0, tempo(500_000)), (0, end_of_track())]),
+            track(vec![
+                (0, note_on(9, 36, 100)),
+                (48, note_off(9, 36)),
+                (0, end_of_track()),
+            ]),
+            track(vec![
+                (24, note_on(9, 38, 100)),
+                (48, note_off(9, 38)),
+                (0, end_of_track()),
+            ]),
+        ],
+    );
+    smf.merge_tracks(&[2, 1]).unwrap();
+    assert_eq!(smf.header.nb_tracks, 2);
+    assert_eq!(smf.tracks.len(), 2);
+    let merged = &smf.tracks[1];
+    assert_eq!(ticks(merged), vec![0, 24, 48, 72, 72]);
+    assert_eq!(merged.track_events[1].event, note_on(9, 38, 100));
+    assert_eq!(merged.track_events[2].event, note_off(9, 36));
+    assert!(merged.ends_properly());
+    assert_eq!(merged.length, merged.computed_length().unwrap());
+}
+
+#[test]
+fn merge_tracks_out_of_range() {
+    let mut smf = smf(
+        Format::MultipleTrack,
+        vec![track(vec![(0, end_of_track())])],
+    );
+    assert!(smf.merge_tracks(&[0, 3]).is_err());
+}
+
+#[test]
+fn songs() {
+    let tracks = vec![
+        track(vec![
+            (0, note_on(0, 60, 100)),
+            (96, note_off(0, 60)),
+            (0, end_of_track()),
+        ]),
+        track(vec![
+            (0, note_on(1, 64, 100)),
+            (48, note_off(1, 64)),
+            (0, end_of_track()),
+        ]),
+    ];
+    let multiple_song = smf(Format::MultipleSong, tracks.clone());
+    let songs = multiple_song.songs().unwrap();
+    assert_eq!(songs.len(), 2);
+    assert_eq!(*songs[0], tracks[0]);
+    assert_eq!(*songs[1], tracks[1]);
+    let multiple_track = smf(Format::MultipleTrack, tracks);
+    let songs = multiple_track.songs().unwrap();
+    assert_eq!(songs.len(), 1);
+    assert_eq!(ticks(&songs[0]), vec![0, 0, 48, 96, 96]);
+}
+
+#[test]
+fn resample_division() {
+    let mut smf = smf(
+        Format::SingleTrack,
+        vec![track(vec![
+            (0, note_on(0, 60, 100)),
+            (95, note_off(0, 60)),
+            (1, note_on(0, 62, 100)),
+            (96, note_off(0, 62)),
+            (0, end_of_track()),
+        ])],
+    );
+    let length = smf.length_ticks();
+    smf.resample_division(480).unwrap();
+    assert_eq!(
+        smf.header.time_division,
+        TimeScale::TicksPerQuarterNote(480)
+    );
+    assert_eq!(smf.length_ticks(), length * 5);
+    assert_eq!(ticks(&smf.tracks[0]), vec![0, 475, 480, 960, 960]);
+    let track = &smf.tracks[0];
+    assert_eq!(track.length, track.computed_length().unwrap());
+    // Going back gives the original times
+    smf.resample_division(96).unwrap();
+    assert_eq!(ticks(&smf.tracks[0]), vec![0, 95, 96, 192, 192]);
+}
+
+#[test]
+fn resample_division_smpte() {
+    let mut smf = smf(Format::SingleTrack, vec![track(vec![(0, end_of_track())])]);
+    smf.header.time_division = TimeScale::SMPTECompatible(-25, 40);
+    assert!(smf.resample_division(480).is_err());
+}
+
+#[test]
+fn quantize_durations() {
+    let mut track = track(vec![
+        (0, note_on(0, 60, 100)),
+        (40, note_off(0, 60)),
+        (8, note_on(0, 62, 100)),
+        (4, note_off(0, 62)),
+        (44, note_on(0, 60, 100)),
+        (80, note_off(0, 60)),
+        (0, end_of_track()),
+    ]);
+    // Eighth notes at 96 ticks per quarter note
+    track.quantize_durations(48, 24).unwrap();
+    // The first note lasts an eighth, the second is kept at the minimum, the third is rounded up
+    assert_eq!(ticks(&track), vec![0, 48, 48, 72, 96, 192, 192]);
+    assert_eq!(track.track_events[1].event, note_off(0, 60));
+    assert_eq!(track.track_events[2].event, note_on(0, 62, 100));
+    assert!(track.ends_properly());
+    assert_eq!(track.length, track.computed_length().unwrap());
+}
+
+#[test]
+fn quantize_durations_overlap() {
+    let mut track = track(vec![
+        (0, note_on(0, 60, 100)),
+        (70, note_off(0, 60)),
+        (10, note_on(0, 60, 100)),
+        (20, note_off(0, 60)),
+        (0, end_of_track()),
+    ]);
+    // The first note would end at 96, after the same key is played again
+    track.quantize_durations(96, 24).unwrap();
+    assert_eq!(ticks(&track), vec![0, 80, 80, 104, 104]);
+    assert_eq!(track.track_events[1].event, note_off(0, 60));
+}
+
+#[test]
+fn quantize_durations_same_tick() {
+    let mut track = track(vec![
+        (0, note_on(0, 60, 100)),
+        (0, note_on(0, 60, 90)),
+        (48, Event::Marker(text_message("Verse"))),
+        (12, note_off(0, 60)),
+        (0, note_off(0, 60)),
+        (0, end_of_track()),
+    ]);
+    // Both notes start together, so the first one can't be shortened to nothing
+    track.quantize_durations(48, 0).unwrap();
+    assert_eq!(ticks(&track), vec![0, 0, 1, 48, 48, 60]);
+    assert_eq!(track.track_events[2].event, note_off(0, 60));
+    // The Marker stays before the Note Off that ends at the same tick
+    assert_eq!(
+        track.track_events[3].event,
+        Event::Marker(text_message("Verse"))
+    );
+    assert_eq!(track.track_events[4].event, note_off(0, 60));
+}
+
+#[test]
+fn to_single_track_channel_prefixes() {
+    let smf = smf(
+        Format::MultipleTrack,
+        vec![
+            track(vec![(0, tempo(500_000)), (0, end_of_track())]),
+            track(vec![
+                (0, instrument_name("Bass")),
+                (0, note_on(2, 40, 100)),
+                (96, note_off(2, 40)),
+                (0, end_of_track()),
+            ]),
+            track(vec![
+                (0, instrument_name("Lead")),
+                (48, note_on(5, 72, 100)),
+                (96, note_off(5, 72)),
+                (0, end_of_track()),
+            ]),
+        ],
+    );
+    let single = smf.to_single_track(true).unwrap();
+    assert_eq!(single.header.format, Format::SingleTrack);
+    assert_eq!(single.header.nb_tracks, 1);
+    let events: Vec<Event> = single.tracks[0]
+        .iter_absolute()
+        .map(|(_, e)| e.clone())
+        .collect();
+    assert_eq!(
+        events,
+        vec![
+            tempo(500_000),
+            channel_prefix(2),
+            instrument_name("Bass"),
+            note_on(2, 40, 100),
+            channel_prefix(5),
+            instrument_name("Lead"),
+            note_on(5, 72, 100),
+            channel_prefix(2),
+            note_off(2, 40),
+            channel_prefix(5),
+            note_off(5, 72),
+            end_of_track(),
+        ]
+    );
+    assert_eq!(ticks(&single.tracks[0])[10], 144);
+    let plain = smf.to_single_track(false).unwrap();
+    assert_eq!(plain.tracks[0].track_events.len(), 8);
+}
+
+#[test]
+fn slice() {
+    let program = Event::ProgramChange(ProgramChange {
+        channel: 0,
+        program: 40,
+    });
+    let smf = smf(
+        Format::MultipleTrack,
+        vec![
+            track(vec![
+                (0, tempo(500_000)),
+                (96, tempo(400_000)),
+                (192, tempo(300_000)),
+                (96, end_of_track()),
+            ]),
+            track(vec![
+                (0, program.clone()),
+                (0, note_on(0, 60, 100)),
+                (48, note_off(0, 60)),
+                (144, note_on(0, 62, 100)),
+                (48, note_off(0, 62)),
+                (144, end_of_track()),
+            ]),
+        ],
+    );
+    let slice = smf.slice(144, 288).unwrap();
+    assert_eq!(slice.header, smf.header);
+    let conductor: Vec<Event> = slice.tracks[0]
+        .iter_absolute()
+        .map(|(_, e)| e.clone())
+        .collect();
+    assert_eq!(conductor, vec![tempo(400_000), end_of_track()]);
+    assert_eq!(ticks(&slice.tracks[0]), vec![0, 144]);
+    let notes: Vec<Event> = slice.tracks[1]
+        .iter_absolute()
+        .map(|(_, e)| e.clone())
+        .collect();
+    assert_eq!(
+        notes,
+        vec![
+            program,
+            note_on(0, 62, 100),
+            note_off(0, 62),
+            end_of_track()
+        ]
+    );
+    assert_eq!(ticks(&slice.tracks[1]), vec![0, 48, 96, 144]);
+    assert!(smf.slice(10, 5).is_err());
+    // A channel out of range does not panic
+    let odd_program = Event::ProgramChange(ProgramChange {
+        channel: 0xFF,
+        program: 1,
+    });
+    let odd = common::smf(
+        Format::SingleTrack,
+        vec![track(vec![(0, odd_program.clone()), (96, end_of_track())])],
+    );
+    let slice = odd.slice(48, 96).unwrap();
+    assert_eq!(slice.tracks[0].track_events[0].event, odd_program);
+}
+
+#[test]
+fn compact() {
+    let mut smf = smf(
+        Format::MultipleTrack,
+        vec![
+            track(vec![(0, tempo(500_000)), (0, end_of_track())]),
+            track(vec![(0, end_of_track())]),
+            track(vec![
+                (0, note_on(0, 60, 100)),
+                (96, note_off(0, 60)),
+                (0, end_of_track()),
+            ]),
+        ],
+    );
+    smf.compact().unwrap();
+    assert_eq!(smf.header.nb_tracks, 2);
+    assert_eq!(smf.tracks.len(), 2);
+    assert_eq!(smf.tracks[1].track_events[0].event, note_on(0, 60, 100));
+}
+
+#[test]
+fn compact_single_track() {
+    let mut single = smf(
+        Format::SingleTrack,
+        vec![
+            track(vec![
+                (0, note_on(0, 60, 100)),
+                (96, note_off(0, 60)),
+                (0, end_of_track()),
+            ]),
+            track(vec![(48, tempo(400_000)), (0, end_of_track())]),
+        ],
+    );
+    single.compact().unwrap();
+    assert_eq!(single.header.nb_tracks, 1);
+    assert_eq!(ticks(&single.tracks[0]), vec![0, 48, 96, 96]);
+    let mut empty = smf(
+        Format::MultipleTrack,
+        vec![track(vec![(0, end_of_track())])],
+    );
+    empty.compact().unwrap();
+    assert_eq!(empty.header.nb_tracks, 1);
+}
+
+#[test]
+fn map_events() {
+    let mut doubled = track(vec![
+        (0, note_on(0, 60, 100)),
+        (48, note_off(0, 60)),
+        (24, end_of_track()),
+    ]);
+    doubled
+        .map_events(|delta, event| Some((delta * 2, event)))
+        .unwrap();
+    assert_eq!(ticks(&doubled), vec![0, 96, 144]);
+    assert_eq!(doubled.length, doubled.computed_length().unwrap());
+    // Dropping an event keeps the next ones in place
+    let mut dropped = track(vec![
+        (0, tempo(500_000)),
+        (48, note_on(0, 60, 100)),
+        (48, note_off(0, 60)),
+        (0, end_of_track()),
+    ]);
+    dropped
+        .map_events(|delta, event| match event {
+            Event::NoteOn(_) => None,
+            e => Some((delta, e)),
+        })
+        .unwrap();
+    assert_eq!(ticks(&dropped), vec![0, 96, 96]);
+    // Carrying a Delta-Time too large to be written fails, leaving the track as it was
+    let mut far = track(vec![
+        (0x0FFF_FFFF, note_on(0, 60, 100)),
+        (0x0FFF_FFFF, note_off(0, 60)),
+        (0, end_of_track()),
+    ]);
+    let original = far.clone();
+    assert!(far.remove_channel(0).is_err());
+    assert_eq!(far, original);
+}
+
+#[test]
+fn map_tracks() {
+    let mut smf = smf(
+        Format::MultipleTrack,
+        vec![
+            track(vec![
+                (0, note_on(9, 36, 100)),
+                (96, note_off(9, 36)),
+                (0, end_of_track()),
+            ]),
+            track(vec![
+                (0, note_on(0, 60, 100)),
+                (96, note_off(0, 60)),
+                (0, end_of_track()),
+            ]),
+            track(vec![
+                (0, note_on(1, 48, 100)),
+                (96, note_off(1, 48)),
+                (0, end_of_track()),
+            ]),
+        ],
+    );
+    smf.map_tracks(|index, track| {
+        if index != 0 {
+            track.for_each_event_mut(|event| {
+                if let Event::NoteOn(n) | Event::NoteOff(n) = event {
+                    n.key += 12;
+                }
+            });
+        }
+    })
+    .unwrap();
+    let keys: Vec<u8> = smf
+        .tracks
+        .iter()
+        .map(|t| match &t.track_events[0].event {
+            Event::NoteOn(n) => n.key,
+            e => panic!("Expected a NoteOn, got {:?}", e),
+        })
+        .collect();
+    assert_eq!(keys, vec![36, 72, 60]);
+    assert!(smf.to_bytes().is_ok());
+}
+
+#[test]
+fn map_tempos() {
+    let tempo = |value| Event::Tempo(Tempo { value });
+    let mut file = smf(
+        Format::MultipleTrack,
+        vec![
+            track(vec![
+                (0, tempo(500_000)),
+                (96, tempo(400_001)),
+                (0, end_of_track()),
+            ]),
+            track(vec![(0, tempo(0xFF_FFFF)), (0, end_of_track())]),
+        ],
+    );
+    file.map_tempos(|value| value / 2);
+    let tempos: Vec<u32> = file
+        .iter_absolute()
+        .filter_map(|(_, _, event)| match event {
+            Event::Tempo(t) => Some(t.value),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(tempos, vec![250_000, 200_000, 0x7F_FFFF]);
+    // Out of range results are clamped
+    file.map_tempos(|value| value * 4);
+    assert_eq!(file.tracks[1].track_events[0].event, tempo(0xFF_FFFF));
+    file.map_tempos(|_| 0);
+    assert_eq!(file.tracks[0].track_events[0].event, tempo(1));
+}
+
+#[test]
+fn add_click_track() {
+    let four_four = Event::TimeSignature(TimeSignature::new(4, 2, 24, 8));
+    // Two measures of 4/4
+    let mut file = smf(
+        Format::SingleTrack,
+        vec![track(vec![
+            (0, four_four),
+            (0, note_on(0, 60, 100)),
+            (768, note_off(0, 60)),
+            (0, end_of_track()),
+        ])],
+    );
+    file.add_click_track(9, 37, 76).unwrap();
+    assert_eq!(file.header.format, Format::MultipleTrack);
+    assert_eq!(file.header.nb_tracks, 2);
+    let clicks: Vec<(u64, u8, u8)> = file.tracks[1]
+        .iter_absolute()
+        .filter_map(|(tick, event)| match event {
+            Event::NoteOn(n) => Some((tick, n.key, n.velocity)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        clicks,
+        vec![
+            (0, 76, 127),
+            (96, 37, 100),
+            (192, 37, 100),
+            (288, 37, 100),
+            (384, 76, 127),
+            (480, 37, 100),
+            (576, 37, 100),
+            (672, 37, 100),
+        ]
+    );
+    assert_eq!(file.tracks[1].note_balance()[9], 0);
+    assert!(file.to_bytes().is_ok());
+    // Nothing is added for a channel or a note that can't be written
+    assert!(matches!(
+        file.add_click_track(16, 37, 76),
+        Err(SMFError::InvalidChannel(16))
+    ));
+    assert!(matches!(
+        file.add_click_track(9, 37, 128),
+        Err(SMFError::InvalidDataByte(128))
+    ));
+    assert_eq!(file.tracks.len(), 2);
+    file.header.time_division = TimeScale::SMPTECompatible(-25, 40);
+    assert!(file.add_click_track(9, 37, 76).is_err());
+}
+
+#[test]
+fn transpose() {
+    let mut melody = track(vec![
+        (0, note_on(0, 60, 100)),
+        (0, note_on(9, 36, 100)),
+        (0, note_on(1, 125, 100)),
+        (96, note_off(0, 60)),
+        (0, note_off(9, 36)),
+        (0, note_off(1, 125)),
+        (0, end_of_track()),
+    ]);
+    let keys = |track: &standard_midi_file::track::SMFTrack| -> Vec<u8> {
+        track
+            .track_events
+            .iter()
+            .filter_map(|t| match &t.event {
+                Event::NoteOn(n) | Event::NoteOff(n) => Some(n.key),
+                _ => None,
+            })
+            .collect()
+    };
+    let mut everything = melody.clone();
+    melody.transpose(5, true);
+    assert_eq!(keys(&melody), vec![65, 36, 127, 65, 36, 127]);
+    everything.transpose(-5, false);
+    assert_eq!(keys(&everything), vec![55, 31, 120, 55, 31, 120]);
+}
+
+#[test]
+fn reverse() {
+    let mut melody = track(vec![
+        (0, note_on(0, 60, 100)),
+        (96, note_off(0, 60)),
+        (0, note_on(0, 62, 90)),
+        (192, note_off(0, 62)),
+        (0, end_of_track()),
+    ]);
+    melody.reverse().unwrap();
+    assert_eq!(
+        melody.iter_absolute().collect::<Vec<_>>(),
+        vec![
+            (0, &note_on(0, 62, 90)),
+            (192, &note_off(0, 62)),
+            (192, &note_on(0, 60, 100)),
+            (288, &note_off(0, 60)),
+            (288, &end_of_track()),
+        ]
+    );
+    assert_eq!(melody.length, melody.computed_length().unwrap());
+}
+
+#[test]
+fn dedup_simultaneous_notes() {
+    let mut melody = track(vec![
+        (0, note_on(0, 60, 100)),
+        (0, note_on(0, 60, 80)),
+        (0, note_on(1, 60, 100)),
+        (96, note_off(0, 60)),
+        (0, note_off(0, 60)),
+        (0, note_off(1, 60)),
+        // Repeated later without a release: left alone
+        (0, note_on(0, 62, 100)),
+        (48, note_on(0, 62, 100)),
+        (48, note_off(0, 62)),
+        (0, note_off(0, 62)),
+        (0, end_of_track()),
+    ]);
+    melody.dedup_simultaneous_notes().unwrap();
+    assert_eq!(
+        melody.iter_absolute().collect::<Vec<_>>(),
+        vec![
+            (0, &note_on(0, 60, 100)),
+            (0, &note_on(1, 60, 100)),
+            (96, &note_off(0, 60)),
+            (96, &note_off(1, 60)),
+            (96, &note_on(0, 62, 100)),
+            (144, &note_on(0, 62, 100)),
+            (192, &note_off(0, 62)),
+            (192, &note_off(0, 62)),
+            (192, &end_of_track()),
+        ]
+    );
+    assert_eq!(melody.length, melody.computed_length().unwrap());
+}
+
+#[test]
+fn set_program() {
+    let program = |channel, program| Event::ProgramChange(ProgramChange { channel, program });
+    let bank_select = |channel, value| {
+        Event::ControllerChange(ControllerChange {
+            channel,
+            controller_number: 0,
+            value,
+        })
+    };
+    let mut piano = track(vec![
+        (0, instrument_name("Piano")),
+        (0, bank_select(2, 1)),
+        (0, note_on(2, 60, 100)),
+        (96, note_off(2, 60)),
+        (0, end_of_track()),
+    ]);
+    piano.set_program(2, 5).unwrap();
+    assert_eq!(piano.track_events[2].delta_time.value, 0);
+    assert_eq!(piano.track_events[2].event, program(2, 5));
+    assert_eq!(piano.length, piano.computed_length().unwrap());
+    // The Program Change is updated afterwards
+    piano.set_program(2, 6).unwrap();
+    assert_eq!(piano.track_events.len(), 6);
+    assert_eq!(piano.track_events[2].event, program(2, 6));
+    // Without Bank Select, it goes at the front
+    piano.set_program(3, 7).unwrap();
+    assert_eq!(piano.track_events[0].event, program(3, 7));
+    assert_eq!(piano.track_events[0].delta_time.value, 0);
+}
+
+#[test]
+fn remove_channel() {
+    let mut song = track(vec![
+        (0, channel_prefix(9)),
+        (0, note_on(9, 36, 100)),
+        (0, note_on(0, 60, 100)),
+        (48, note_off(9, 36)),
+        (48, note_off(0, 60)),
+        (0, note_on(9, 38, 100)),
+        (24, note_on(0, 62, 100)),
+        (72, note_off(0, 62)),
+        (0, note_off(9, 38)),
+        (0, end_of_track()),
+    ]);
+    song.remove_channel(9).unwrap();
+    assert!(song
+        .track_events
+        .iter()
+        .all(|t| t.event.channel() == Some(0) || t.event == end_of_track()));
+    assert_eq!(ticks(&song), vec![0, 96, 120, 192, 192]);
+    assert_eq!(song.length, song.computed_length().unwrap());
+}
+
+#[test]
+fn trim_leading_silence() {
+    let mut smf = smf(
+        Format::MultipleTrack,
+        vec![
+            track(vec![
+                (0, tempo(400_000)),
+                (120, tempo(500_000)),
+                (480, end_of_track()),
+            ]),
+            track(vec![
+                (0, instrument_name("Piano")),
+                (240, note_on(0, 60, 100)),
+                (96, note_off(0, 60)),
+                (0, end_of_track()),
+            ]),
+            track(vec![
+                (300, note_on(1, 48, 100)),
+                (96, note_off(1, 48)),
+                (0, end_of_track()),
+            ]),
+        ],
+    );
+    smf.trim_leading_silence().unwrap();
+    assert_eq!(ticks(&smf.tracks[0]), vec![0, 0, 360]);
+    assert_eq!(ticks(&smf.tracks[1]), vec![0, 0, 96, 96]);
+    assert_eq!(ticks(&smf.tracks[2]), vec![60, 156, 156]);
+    assert!(smf.to_bytes().is_ok());
+}
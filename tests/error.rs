@@ -0,0 +1,17 @@
+use standard_midi_file::header::SMFHeader;
+use standard_midi_file::vlv::VLV;
+use std::io::ErrorKind;
+
+#[test]
+fn io_kind_truncated() {
+    // Header stops in the middle of the number of tracks
+    let header = &[b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 0, 0];
+    let error = SMFHeader::import(&mut &header[..]).err().unwrap();
+    assert_eq!(error.io_kind(), Some(ErrorKind::UnexpectedEof));
+}
+
+#[test]
+fn io_kind_other() {
+    let error = VLV::new(u32::MAX).err().unwrap();
+    assert_eq!(error.io_kind(), None);
+}
@@ -0,0 +1,321 @@
+use standard_midi_file::error::SMFError;
+use standard_midi_file::track::event::*;
+use std::borrow::Cow;
+
+#[test]
+fn smpte_offset_one_hour() {
+    let offset = SMPTEOffset {
+        hours: 1,
+        minutes: 0,
+        seconds: 0,
+        frames: 0,
+        fractional_frames: 0,
+    };
+    assert_eq!(offset.as_seconds(25.0), 3600.0);
+    assert_eq!(SMPTEOffset::from_seconds(3600.0, 25.0).unwrap(), offset);
+}
+
+#[test]
+fn smpte_offset_sub_second() {
+    // 12 frames and a half at 24 fps
+    let offset = SMPTEOffset::from_seconds(0.5208333, 24.0).unwrap();
+    assert_eq!(offset.seconds, 0);
+    assert_eq!(offset.frames, 12);
+    assert_eq!(offset.fractional_frames, 50);
+    assert!((offset.as_seconds(24.0) - 0.5208333).abs() < 1e-6);
+}
+
+#[test]
+fn smpte_offset_out_of_range() {
+    assert!(SMPTEOffset::from_seconds(-1.0, 30.0).is_err());
+    assert!(SMPTEOffset::from_seconds(86400.0, 30.0).is_err());
+}
+
+#[test]
+fn smpte_offset_timecode() {
+    let offset = SMPTEOffset::from_timecode("01:00:00:00.00", SmpteFrameRate::Fps25).unwrap();
+    assert_eq!(offset.hours, 0b0010_0001);
+    assert_eq!(offset.frame_rate(), SmpteFrameRate::Fps25);
+    assert_eq!(offset.as_seconds(25.0), 3600.0);
+    assert_eq!(offset.to_timecode(), "01:00:00:00.00");
+    let offset = SMPTEOffset::from_timecode("23:59:59:29", SmpteFrameRate::Fps29_97).unwrap();
+    assert_eq!(offset.to_timecode(), "23:59:59:29.00");
+    // 25 frames is too many at 25 fps
+    match SMPTEOffset::from_timecode("00:00:00:25", SmpteFrameRate::Fps25) {
+        Err(SMFError::SMPTEOffsetOutOfRange) => {}
+        r => panic!("Expected SMPTEOffsetOutOfRange, got {:?}", r),
+    }
+    assert!(SMPTEOffset::from_timecode("01:00:00", SmpteFrameRate::Fps24).is_err());
+    assert!(SMPTEOffset::from_timecode("01:00:00:aa", SmpteFrameRate::Fps24).is_err());
+}
+
+#[test]
+fn clean_text() {
+    let message = TextMessage::new("Piano\0 1\r\nLeft\x07 hand").unwrap();
+    assert_eq!(message.clean_text(), "Piano 1\nLeft hand");
+}
+
+#[test]
+fn stray_clock() {
+    let data = [0xF8, 0x90, 0x3C, 0x40];
+    match Event::import(&mut &data[..], None) {
+        Err(SMFError::InvalidInSmf(0xF8)) => {}
+        Err(e) => panic!("Unexpected error: {}", e),
+        Ok(_) => panic!("Clock message was accepted"),
+    }
+}
+
+#[test]
+fn sequencer_specific_round_trip() {
+    let data = [0xFF, 0x7F, 4, 0x00, 0x00, 0x41, 0x01];
+    let (event, _) = Event::import(&mut &data[..], None).unwrap();
+    let mut meta = match &event {
+        Event::SequencerSpecificEvent(m) => m.clone(),
+        e => panic!("Expected a SequencerSpecificEvent, got {:?}", e),
+    };
+    assert_eq!(meta.meta_type, 0x7F);
+    assert_eq!(event.get_length().unwrap(), data.len() as u32);
+    assert_eq!(event.to_bytes().unwrap(), data.to_vec());
+    // Always written as a Sequencer Specific event, whatever its meta_type says
+    meta.meta_type = 0x60;
+    assert_eq!(
+        Event::SequencerSpecificEvent(meta).to_bytes().unwrap(),
+        data.to_vec()
+    );
+}
+
+#[test]
+fn sequencer_specific_set_data() {
+    let data = [0xFF, 0x7F, 3, 0x00, 0x00, 0x41];
+    let (event, _) = Event::import(&mut &data[..], None).unwrap();
+    let mut meta = match event {
+        Event::SequencerSpecificEvent(m) | Event::UnknownMetaEvent(m) => m,
+        e => panic!("Expected a Meta Event, got {:?}", e),
+    };
+    meta.set_data(vec![0x43, 0x10, 0x4C, 0x00]).unwrap();
+    let mut exported = Vec::new();
+    Event::SequencerSpecificEvent(meta.clone())
+        .export(&mut exported)
+        .unwrap();
+    assert_eq!(exported, vec![0xFF, 0x7F, 4, 0x43, 0x10, 0x4C, 0x00]);
+    // Editing the data directly still writes the right length
+    meta.data.push(0x7F);
+    exported.clear();
+    meta.export(&mut exported).unwrap();
+    assert_eq!(exported, vec![0xFF, 0x7F, 5, 0x43, 0x10, 0x4C, 0x00, 0x7F]);
+}
+
+#[test]
+fn tempo_extra_bytes() {
+    // A Tempo with one byte too many, followed by an End of Track
+    let data = [0xFF, 0x51, 4, 0x07, 0xA1, 0x20, 0x00, 0xFF, 0x2F, 0];
+    let mut reader = &data[..];
+    let (tempo, _) = Event::import(&mut reader, None).unwrap();
+    assert_eq!(tempo, Event::Tempo(Tempo { value: 500_000 }));
+    let (end, _) = Event::import(&mut reader, None).unwrap();
+    assert_eq!(end, Event::EndOfTrack(EndOfTrack {}));
+}
+
+#[test]
+fn sysex_split() {
+    let mut data: Vec<u8> = (0..299).map(|i| (i % 0x80) as u8).collect();
+    data.push(0xF7);
+    let sysex = SystemExclusive {
+        length: standard_midi_file::vlv::VLV::new(300).unwrap(),
+        data,
+    };
+    let packets = sysex.split(128).unwrap();
+    assert_eq!(packets.len(), 3);
+    let sizes: Vec<u32> = packets.iter().map(|p| p.length.value).collect();
+    assert_eq!(sizes, vec![128, 128, 44]);
+    assert!(packets
+        .iter()
+        .all(|p| p.data.len() == p.length.value as usize));
+    assert_eq!(packets[2].data.last(), Some(&0xF7));
+    let joined: Vec<u8> = packets.iter().flat_map(|p| p.data.clone()).collect();
+    assert_eq!(joined, sysex.data);
+}
+
+#[test]
+fn to_midi_bytes() {
+    let note = NoteChange {
+        channel: 2,
+        key: 60,
+        velocity: 100,
+    };
+    assert_eq!(note.to_midi_bytes(true), [0x92, 60, 100]);
+    assert_eq!(note.to_midi_bytes(false), [0x82, 60, 100]);
+    let program = ProgramChange {
+        channel: 9,
+        program: 25,
+    };
+    assert_eq!(program.to_midi_bytes(), [0xC9, 25]);
+}
+
+#[test]
+fn pitch_bend() {
+    // Center, minimum and maximum, the LSB coming first
+    for (bytes, value) in [
+        ([0xE3, 0x00, 0x40], 0x2000),
+        ([0xE3, 0x00, 0x00], 0),
+        ([0xE3, 0x7F, 0x7F], 0x3FFF),
+    ] {
+        match Event::import(&mut &bytes[..], None).unwrap().0 {
+            Event::PitchBend(p) => {
+                assert_eq!((p.channel, p.value), (3, value));
+                assert_eq!(p.to_midi_bytes(), bytes);
+            }
+            e => panic!("Expected a PitchBend, got {:?}", e),
+        }
+    }
+}
+
+#[test]
+fn time_signature_new() {
+    let six_eight = TimeSignature::new(6, 3, 36, 8);
+    assert_eq!(six_eight.numerator, 6);
+    assert_eq!(six_eight.denominator, 3);
+    assert_eq!(six_eight.clocks_between_metronome_clicks, 36);
+    assert_eq!(six_eight.thirty_seconds_per_quarter, 8);
+    let mut data = Vec::new();
+    Event::TimeSignature(six_eight).export(&mut data).unwrap();
+    assert_eq!(data, vec![0xFF, 0x58, 4, 6, 3, 36, 8]);
+}
+
+#[test]
+fn meta_under_length() {
+    // A Tempo declaring 2 bytes instead of 3
+    let data = [0xFF, 0x51, 2, 0x07, 0xA1, 0x20, 0x00, 0xFF, 0x2F, 0];
+    match Event::import(&mut &data[..], None) {
+        Err(SMFError::UnexpectedMetaEventLength(2)) => {}
+        r => panic!("Expected UnexpectedMetaEventLength, got {:?}", r),
+    }
+    // At the end of the stream, reading the fields fails first
+    let error = Event::import(&mut &data[..5], None).unwrap_err();
+    assert_eq!(error.io_kind(), Some(std::io::ErrorKind::UnexpectedEof));
+}
+
+#[test]
+fn meta_over_length() {
+    // A Time Signature with two bytes of padding, followed by an End of Track
+    let data = [0xFF, 0x58, 6, 4, 2, 24, 8, 0xAA, 0xBB, 0xFF, 0x2F, 0];
+    let mut reader = &data[..];
+    let (time_signature, _) = Event::import(&mut reader, None).unwrap();
+    assert_eq!(
+        time_signature,
+        Event::TimeSignature(TimeSignature::new(4, 2, 24, 8))
+    );
+    let (end, _) = Event::import(&mut reader, None).unwrap();
+    assert_eq!(end, Event::EndOfTrack(EndOfTrack {}));
+}
+
+#[test]
+fn to_bytes() {
+    let note_on = Event::NoteOn(NoteChange {
+        channel: 1,
+        key: 0x3C,
+        velocity: 0x40,
+    });
+    assert_eq!(note_on.to_bytes().unwrap(), vec![0x91, 0x3C, 0x40]);
+    let program = Event::ProgramChange(ProgramChange {
+        channel: 0,
+        program: 5,
+    });
+    assert_eq!(program.to_bytes().unwrap(), vec![0xC0, 5]);
+    let tempo = Event::Tempo(Tempo { value: 500_000 });
+    assert_eq!(
+        tempo.to_bytes().unwrap(),
+        vec![0xFF, 0x51, 3, 0x07, 0xA1, 0x20]
+    );
+    let end = Event::EndOfTrack(EndOfTrack {});
+    assert_eq!(end.to_bytes().unwrap(), vec![0xFF, 0x2F, 0]);
+    let text = Event::Text(TextMessage::new("Hi").unwrap());
+    assert_eq!(text.to_bytes().unwrap(), vec![0xFF, 0x01, 2, b'H', b'i']);
+}
+
+#[test]
+fn other_text_keeps_type() {
+    let data = [0xFF, 0x0A, 5, b'B', b'a', b's', b's', b'!'];
+    let (event, _) = Event::import(&mut &data[..], None).unwrap();
+    match &event {
+        Event::OtherText(o) => {
+            assert_eq!(o.meta_type, 0x0A);
+            assert_eq!(o.message.text, "Bass!");
+        }
+        e => panic!("Expected OtherText, got {:?}", e),
+    }
+    assert_eq!(event.kind(), EventKind::OtherText);
+    assert_eq!(event.to_bytes().unwrap(), data.to_vec());
+    // Types outside of the text range stay generic, with their type
+    let data = [0xFF, 0x60, 1, 0x42];
+    match Event::import(&mut &data[..], None).unwrap().0 {
+        Event::UnknownMetaEvent(u) => assert_eq!(u.meta_type, 0x60),
+        e => panic!("Expected UnknownMetaEvent, got {:?}", e),
+    }
+}
+
+#[test]
+fn text_cow() {
+    let (event, _) = Event::import(
+        &mut &[0xFF, 0x01, 5, b'H', b'e', b'l', b'l', b'o'][..],
+        None,
+    )
+    .unwrap();
+    let message = event.text_message().unwrap();
+    assert!(matches!(message.text_cow(), Cow::Borrowed("Hello")));
+    // Latin-1 "é" is not valid UTF-8, and is written back unchanged
+    let data = [0xFF, 0x01, 4, b'C', b'a', b'f', 0xE9];
+    let (event, _) = Event::import(&mut &data[..], None).unwrap();
+    let message = event.text_message().unwrap();
+    assert!(matches!(message.text_cow(), Cow::Owned(_)));
+    assert_eq!(message.text_cow(), "Caf\u{FFFD}");
+    assert_eq!(event.to_bytes().unwrap(), data.to_vec());
+}
+
+#[test]
+fn tempo_from_bpm() {
+    assert_eq!(Tempo::from_bpm(120.0).unwrap().value, 500_000);
+    assert_eq!(Tempo::from_bpm(300.0).unwrap().value, 200_000);
+    // 631578.947..., rounded up
+    assert_eq!(Tempo::from_bpm(95.0).unwrap().value, 631_579);
+    match Tempo::from_bpm(1.0) {
+        Err(SMFError::TempoOutOfRange(bpm)) => assert_eq!(bpm, 1.0),
+        r => panic!("Expected TempoOutOfRange, got {:?}", r),
+    }
+    assert!(Tempo::from_bpm(0.0).is_err());
+    assert!(Tempo::from_bpm(f64::NAN).is_err());
+}
+
+#[test]
+fn describe() {
+    let note_on = Event::NoteOn(NoteChange {
+        channel: 0,
+        key: 60,
+        velocity: 100,
+    });
+    assert_eq!(note_on.describe(Verbosity::Brief), "NoteOn");
+    assert_eq!(note_on.describe(Verbosity::Normal), "NoteOn ch1 C4");
+    assert_eq!(
+        note_on.describe(Verbosity::Verbose),
+        "NoteOn ch1 C4 NoteOn(NoteChange { channel: 0, key: 60, velocity: 100 }) [90 3C 64]"
+    );
+    let lyric = Event::Lyric(TextMessage::new("la").unwrap());
+    assert_eq!(lyric.describe(Verbosity::Normal), "Lyric \"la\"");
+    let low = Event::NoteOff(NoteChange {
+        channel: 9,
+        key: 1,
+        velocity: 0,
+    });
+    assert_eq!(low.describe(Verbosity::Normal), "NoteOff ch10 C#-1");
+    // Channels are not masked on import, and must not overflow
+    let (prefix, _) = Event::import(&mut &[0xFF, 0x20, 1, 0xFF][..], None).unwrap();
+    assert_eq!(
+        prefix.describe(Verbosity::Normal),
+        "MIDIChannelPrefix ch256"
+    );
+    let program = Event::ProgramChange(ProgramChange {
+        channel: 0xFF,
+        program: 1,
+    });
+    assert_eq!(program.describe(Verbosity::Normal), "ProgramChange ch256 1");
+}
@@ -0,0 +1,75 @@
+use standard_midi_file::track::event::Event;
+use standard_midi_file::track::SMFTrack;
+use standard_midi_file::SMF;
+
+/// A valid file with one of most kinds of events, used as a base to mutate
+const VALID: [u8; 75] = [
+    b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 1, 0, 1, 0, 96, // Header
+    b'M', b'T', b'r', b'k', 0, 0, 0, 53, // Track
+    0, 0xFF, 0x51, 3, 0x07, 0xA1, 0x20, // Tempo
+    0, 0xFF, 0x58, 4, 4, 2, 24, 8, // Time Signature
+    0, 0xFF, 0x59, 2, 0xFF, 0, // Key Signature
+    0, 0xFF, 0x03, 4, b'P', b'i', b'a', b'n', // Track Name
+    0, 0xF0, 3, 0x41, 0x10, 0xF7, // SysEx
+    0, 0xC0, 0, // Program Change
+    0, 0x90, 0x3C, 0x40, // Note On
+    0x60, 0x3C, 0x00, // Running Status Note On
+    0, 0xE0, 0x00, 0x40, // Pitch Bend
+    0, 0xFF, 0x2F, 0, // End of Track
+];
+
+/// Small SplitMix64 generator, so failures can be reproduced
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+#[test]
+fn valid_base() {
+    assert!(SMF::import(&mut &VALID[..]).is_ok());
+}
+
+#[test]
+fn random_bytes() {
+    let mut random = Random(1);
+    for _ in 0..2000 {
+        let data: Vec<u8> = (0..random.next() % 256)
+            .map(|_| random.next() as u8)
+            .collect();
+        assert!(SMF::import(&mut &data[..]).is_err());
+        // Also read them as the contents of a track and as single events
+        let mut track = vec![b'M', b'T', b'r', b'k', 0, 0, 0, data.len() as u8];
+        track.extend_from_slice(&data);
+        let _ = SMFTrack::import(&mut &track[..]);
+        let _ = Event::import(&mut &data[..], Some(0x90));
+    }
+}
+
+#[test]
+fn mutated_files() {
+    let mut random = Random(2);
+    for _ in 0..20000 {
+        let mut data = VALID.to_vec();
+        for _ in 0..1 + random.next() % 4 {
+            if data.is_empty() {
+                break;
+            }
+            let i = (random.next() % data.len() as u64) as usize;
+            match random.next() % 4 {
+                0 => data[i] = random.next() as u8,
+                1 => data[i] ^= 0x80,
+                2 => data[i] = 0xFF,
+                _ => data.truncate(i),
+            }
+        }
+        // Only checks that nothing panics, some mutations are still valid files
+        let _ = SMF::import(&mut &data[..]);
+    }
+}
@@ -0,0 +1,39 @@
+#![cfg(feature = "gzip")]
+
+use standard_midi_file::SMF;
+
+/// A single track file playing one note
+const SINGLE_NOTE: [u8; 34] = [
+    b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 0, 0, 1, 0, 96, // Header
+    b'M', b'T', b'r', b'k', 0, 0, 0, 12, // Track
+    0, 0x90, 0x3C, 0x40, // Note On
+    0x60, 0x80, 0x3C, 0x40, // Note Off
+    0, 0xFF, 0x2F, 0, // End of Track
+];
+
+#[test]
+fn gzip_round_trip() {
+    let smf = SMF::from_bytes(&SINGLE_NOTE).unwrap();
+    let mut compressed = Vec::new();
+    smf.export_gz(&mut compressed).unwrap();
+    assert_eq!(compressed[..2], [0x1F, 0x8B]);
+    let read_back = SMF::import_gz(&mut &compressed[..]).unwrap();
+    assert_eq!(read_back.to_bytes().unwrap(), SINGLE_NOTE.to_vec());
+}
+
+#[test]
+fn read_from_path() {
+    let smf = SMF::from_bytes(&SINGLE_NOTE).unwrap();
+    let dir = std::env::temp_dir().join("standard_midi_file_read_from_path");
+    std::fs::create_dir_all(&dir).unwrap();
+    let plain = dir.join("song.mid");
+    std::fs::write(&plain, &SINGLE_NOTE[..]).unwrap();
+    let gzipped = dir.join("song.mid.gz");
+    smf.export_gz(&mut std::fs::File::create(&gzipped).unwrap())
+        .unwrap();
+    let from_plain = SMF::read_from_path(&plain).unwrap();
+    let from_gzipped = SMF::read_from_path(&gzipped).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(from_plain.tracks, smf.tracks);
+    assert_eq!(from_gzipped.tracks, smf.tracks);
+}
@@ -1,4 +1,7 @@
+use standard_midi_file::error::SMFError;
 use standard_midi_file::header::*;
+use standard_midi_file::SMF;
+use std::convert::TryFrom;
 use std::io::Cursor;
 
 #[test]
@@ -25,6 +28,7 @@ fn export() {
         format: Format::MultipleTrack,
         nb_tracks: 5,
         time_division: TimeScale::TicksPerQuarterNote(96),
+        extra: Vec::new(),
     };
     let mut writer = Cursor::new(Vec::new());
     my_header.export(&mut writer).unwrap();
@@ -33,3 +37,74 @@ fn export() {
         vec![b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 1, 0, 5, 0, 96]
     );
 }
+
+#[test]
+fn read_only() {
+    let header = &[b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 0, 0, 1, 1, 0x80];
+    let mut reader = Cursor::new(header);
+    let my_header = SMFHeader::read_only(&mut reader).unwrap();
+    assert_eq!(my_header.nb_tracks, 1);
+    assert_eq!(reader.position(), 0);
+}
+
+#[test]
+fn peek_header() {
+    // The track is garbage, so a full import would fail
+    let data = &[
+        b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 1, 0, 2, 0, 96, b'j', b'u', b'n', b'k',
+    ];
+    let path = std::env::temp_dir().join("standard_midi_file_peek_header.mid");
+    std::fs::write(&path, data).unwrap();
+    let my_header = SMF::peek_header(&path);
+    let full = SMF::import(&mut std::fs::File::open(&path).unwrap());
+    std::fs::remove_file(&path).unwrap();
+    let my_header = my_header.unwrap();
+    assert_eq!(my_header.format, Format::MultipleTrack);
+    assert_eq!(my_header.nb_tracks, 2);
+    assert_eq!(my_header.time_division, TimeScale::TicksPerQuarterNote(96));
+    assert!(full.is_err());
+}
+
+#[test]
+fn extra_bytes_round_trip() {
+    let header = [
+        b'M', b'T', b'h', b'd', 0, 0, 0, 8, 0, 1, 0, 2, 0, 96, 0x12, 0x34,
+    ];
+    let my_header = SMFHeader::import(&mut &header[..]).unwrap();
+    assert_eq!(my_header.length, 8);
+    assert_eq!(my_header.extra, vec![0x12, 0x34]);
+    let mut exported = Vec::new();
+    my_header.export(&mut exported).unwrap();
+    assert_eq!(exported, header.to_vec());
+    // The length follows the extra bytes
+    let mut edited = my_header.clone();
+    edited.extra.push(0x56);
+    let mut exported = Vec::new();
+    edited.export(&mut exported).unwrap();
+    assert_eq!(&exported[4..8], &[0, 0, 0, 9]);
+    assert_eq!(
+        SMFHeader::import(&mut &exported[..]).unwrap().extra.len(),
+        3
+    );
+    // Nothing is written on error
+    edited.nb_tracks = 0;
+    let mut exported = Vec::new();
+    assert!(edited.export(&mut exported).is_err());
+    assert!(exported.is_empty());
+}
+
+#[test]
+fn format_conversions() {
+    for (value, format) in [
+        (0, Format::SingleTrack),
+        (1, Format::MultipleTrack),
+        (2, Format::MultipleSong),
+    ] {
+        assert_eq!(Format::try_from(value).unwrap(), format);
+        assert_eq!(u16::from(format), value);
+    }
+    match Format::try_from(3) {
+        Err(SMFError::UnknownFormat(3)) => {}
+        r => panic!("Expected UnknownFormat, got {:?}", r),
+    }
+}
@@ -0,0 +1,25 @@
+use std::process::Command;
+
+#[test]
+fn aftertouch() {
+    let data = [
+        b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 0, 0, 1, 0, 96, // Header
+        b'M', b'T', b'r', b'k', 0, 0, 0, 23, // Track
+        0, 0x90, 0x3C, 0x40, // Note On
+        0, 0xD0, 0x20, // Channel Pressure
+        0x30, 0xA0, 0x3C, 0x50, // Polyphonic Key Pressure
+        0x30, 0xA0, 0x3C, 0x10, // Polyphonic Key Pressure
+        0, 0x80, 0x3C, 0x40, // Note Off
+        0, 0xFF, 0x2F, 0, // End of Track
+    ];
+    let path = std::env::temp_dir().join("standard_midi_file_midi_info_aftertouch.mid");
+    std::fs::write(&path, &data[..]).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_midi_info"))
+        .arg(&path)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("1 Channel Pressures, 2 Polyphonic Key Pressures"));
+}
@@ -0,0 +1,551 @@
+use standard_midi_file::diff::SMFDifference;
+use standard_midi_file::error::SMFError;
+use standard_midi_file::header::Format;
+use standard_midi_file::options::{ExportOptions, ImportOptions, ImportWarning};
+use standard_midi_file::track::event::{Event, EventKind, ManufacturerId};
+use standard_midi_file::SMF;
+
+/// A single track file playing one note, with 96 ticks per quarter note
+const SINGLE_NOTE: [u8; 34] = [
+    b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 0, 0, 1, 0, 96, // Header
+    b'M', b'T', b'r', b'k', 0, 0, 0, 12, // Track
+    0, 0x90, 0x3C, 0x40, // Note On
+    0x60, 0x80, 0x3C, 0x40, // Note Off
+    0, 0xFF, 0x2F, 0, // End of Track
+];
+
+/// A two track file: a conductor track with a tempo change, and a track playing two notes
+const TWO_TRACKS: [u8; 63] = [
+    b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 1, 0, 2, 0, 96, // Header
+    b'M', b'T', b'r', b'k', 0, 0, 0, 12, // Track 0
+    0, 0xFF, 0x51, 3, 0x07, 0xA1, 0x20, // Tempo, 120 BPM
+    0x83, 0x00, 0xFF, 0x2F, 0, // End of Track at 384
+    b'M', b'T', b'r', b'k', 0, 0, 0, 21, // Track 1
+    0, 0x90, 0x3C, 0x40, // Note On
+    0x60, 0x80, 0x3C, 0x40, // Note Off
+    0, 0x90, 0x3E, 0x40, // Note On
+    0x81, 0x40, 0x80, 0x3E, 0x40, // Note Off
+    0, 0xFF, 0x2F, 0, // End of Track at 288
+];
+
+#[test]
+fn import_all() {
+    let mut data = SINGLE_NOTE.to_vec();
+    data.extend_from_slice(&SINGLE_NOTE);
+    // A slice can only Read, not Seek
+    let smfs = SMF::import_all(&mut &data[..]).unwrap();
+    assert_eq!(smfs.len(), 2);
+    for smf in &smfs {
+        assert_eq!(smf.tracks.len(), 1);
+        assert_eq!(smf.tracks[0].track_events.len(), 3);
+    }
+}
+
+#[test]
+fn import_all_truncated() {
+    let mut data = SINGLE_NOTE.to_vec();
+    data.extend_from_slice(&SINGLE_NOTE[..10]);
+    assert!(SMF::import_all(&mut &data[..]).is_err());
+}
+
+#[test]
+fn diff_transposed() {
+    let smf = SMF::import(&mut &SINGLE_NOTE[..]).unwrap();
+    let mut transposed = smf.clone();
+    for track_event in &mut transposed.tracks[0].track_events {
+        match &mut track_event.event {
+            Event::NoteOn(n) | Event::NoteOff(n) => n.key += 2,
+            _ => {}
+        }
+    }
+    assert!(smf.diff(&smf).is_empty());
+    let differences = smf.diff(&transposed);
+    assert_eq!(differences.len(), 2);
+    match &differences[0] {
+        SMFDifference::Changed {
+            track: 0,
+            tick: 0,
+            old: Event::NoteOn(o),
+            new: Event::NoteOn(n),
+        } => assert_eq!((o.key, n.key), (0x3C, 0x3E)),
+        d => panic!("Unexpected difference: {:?}", d),
+    }
+    match &differences[1] {
+        SMFDifference::Changed {
+            track: 0,
+            tick: 96,
+            old: Event::NoteOff(o),
+            new: Event::NoteOff(n),
+        } => assert_eq!((o.key, n.key), (0x3C, 0x3E)),
+        d => panic!("Unexpected difference: {:?}", d),
+    }
+}
+
+#[test]
+fn sysex_manufacturers() {
+    let data = [
+        b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 0, 0, 1, 0, 96, // Header
+        b'M', b'T', b'r', b'k', 0, 0, 0, 32, // Track
+        0, 0xF0, 5, 0x41, 0x10, 0x42, 0x12, 0xF7, // Roland
+        0, 0xF0, 4, 0x43, 0x10, 0x4C, 0xF7, // Yamaha
+        0, 0xF0, 4, 0x41, 0x10, 0x42, 0xF7, // Roland again
+        0, 0xF7, 4, 0x20, 0x30, 0x40, 0xF7, // Continuation, no ID
+        0, 0xFF, 0x2F, 0, // End of Track
+    ];
+    let smf = SMF::import(&mut &data[..]).unwrap();
+    let manufacturers = smf.sysex_manufacturers();
+    assert_eq!(manufacturers.len(), 2);
+    assert!(manufacturers.contains(&ManufacturerId::Single(0x41)));
+    assert!(manufacturers.contains(&ManufacturerId::Single(0x43)));
+}
+
+#[test]
+fn length_ticks() {
+    let smf = SMF::import(&mut &TWO_TRACKS[..]).unwrap();
+    assert_eq!(smf.length_ticks(), 384);
+    let mut shorter = smf.clone();
+    shorter.tracks.remove(0);
+    assert_eq!(shorter.length_ticks(), 288);
+}
+
+#[test]
+fn export_round_trip() {
+    let mut smf = SMF::import(&mut &TWO_TRACKS[..]).unwrap();
+    for track in &mut smf.tracks {
+        track.normalize().unwrap();
+    }
+    let mut data = Vec::new();
+    smf.export(&mut data).unwrap();
+    assert_eq!(data, TWO_TRACKS.to_vec());
+}
+
+#[test]
+fn export_tracks_separately() {
+    let smf = SMF::import(&mut &TWO_TRACKS[..]).unwrap();
+    let dir = std::env::temp_dir().join("standard_midi_file_export_tracks_separately");
+    std::fs::create_dir_all(&dir).unwrap();
+    let paths = smf.export_tracks_separately(&dir, "track_").unwrap();
+    let files: Vec<SMF> = paths
+        .iter()
+        .map(|p| SMF::import(&mut std::fs::File::open(p).unwrap()).unwrap())
+        .collect();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(files.len(), 2);
+    assert_eq!(paths[1].file_name().unwrap(), "track_1.mid");
+    for file in &files {
+        assert_eq!(file.header.format, Format::SingleTrack);
+        assert_eq!(file.header.nb_tracks, 1);
+        assert_eq!(file.header.time_division, smf.header.time_division);
+    }
+    assert_eq!(files[0].tracks[0], smf.tracks[0]);
+    // The tempo of the conductor track was copied
+    match &files[1].tracks[0].track_events[0].event {
+        Event::Tempo(t) => assert_eq!(t.value, 500_000),
+        e => panic!("Expected a Tempo, got {:?}", e),
+    }
+    assert_eq!(files[1].tracks[0].track_events.len(), 6);
+}
+
+#[test]
+fn programs_per_channel() {
+    let data = [
+        b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 0, 0, 1, 0, 96, // Header
+        b'M', b'T', b'r', b'k', 0, 0, 0, 13, // Track
+        0, 0xC0, 0, // Piano on channel 0
+        0, 0xC1, 40, // Violin on channel 1
+        0x60, 0xC0, 48, // Strings on channel 0
+        0, 0xFF, 0x2F, 0, // End of Track
+    ];
+    let smf = SMF::import(&mut &data[..]).unwrap();
+    let programs = smf.programs_per_channel();
+    assert_eq!(programs[0], vec![0, 48]);
+    assert_eq!(programs[1], vec![40]);
+    assert!(programs[2..].iter().all(|p| p.is_empty()));
+}
+
+#[test]
+fn debug_summary() {
+    let smf = SMF::import(&mut &TWO_TRACKS[..]).unwrap();
+    let summary = format!("{:?}", smf);
+    assert!(summary.contains("nb_tracks: 2"));
+    assert!(summary.contains("(None, 2, 12)"));
+    assert!(!summary.contains("NoteOn"));
+}
+
+#[test]
+fn events_of_kind() {
+    let smf = SMF::import(&mut &TWO_TRACKS[..]).unwrap();
+    let tempos: Vec<(usize, u64, &Event)> = smf.events_of_kind(EventKind::Tempo).collect();
+    assert_eq!(tempos.len(), 1);
+    assert_eq!((tempos[0].0, tempos[0].1), (0, 0));
+    let note_ons: Vec<u64> = smf
+        .events_of_kind(EventKind::NoteOn)
+        .map(|(_, tick, _)| tick)
+        .collect();
+    assert_eq!(note_ons, vec![0, 96]);
+}
+
+#[test]
+fn check_format_consistency() {
+    let mut smf = SMF::import(&mut &TWO_TRACKS[..]).unwrap();
+    assert!(smf.check_format_consistency().is_ok());
+    smf.header.format = Format::SingleTrack;
+    let error = smf.check_format_consistency().unwrap_err();
+    assert!(format!("{}", error).contains("MultipleTrack"));
+    smf.tracks.pop();
+    smf.header.nb_tracks = 1;
+    assert!(smf.check_format_consistency().is_ok());
+}
+
+#[test]
+fn import_limits() {
+    let data = [
+        b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 0, 0, 1, 0, 96, // Header
+        b'M', b'T', b'r', b'k', 0, 0, 0, 12, // Track
+        0, 0xF0, 0xFF, 0xFF, 0xFF, 0x7F, 0x41, 0x10, // SysEx claiming 256 MiB
+        0, 0xFF, 0x2F, 0, // End of Track
+    ];
+    let options = ImportOptions {
+        max_track_bytes: None,
+        max_event_data: Some(1024),
+        ..ImportOptions::default()
+    };
+    match SMF::import_with_options(&mut &data[..], &options) {
+        Err(SMFError::LimitExceeded(declared, 1024)) => assert_eq!(declared, 0x0FFF_FFFF),
+        r => panic!("Expected LimitExceeded, got {:?}", r),
+    }
+    let options = ImportOptions {
+        max_track_bytes: Some(8),
+        max_event_data: None,
+        ..ImportOptions::default()
+    };
+    match SMF::import_with_options(&mut &SINGLE_NOTE[..], &options) {
+        Err(SMFError::LimitExceeded(12, 8)) => {}
+        r => panic!("Expected LimitExceeded, got {:?}", r),
+    }
+}
+
+#[test]
+fn skip_payloads() {
+    // A 64 KiB SysEx followed by a note
+    let sysex_length = 0x10000usize;
+    let track_length = 5 + sysex_length + 12;
+    let mut data = vec![b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 0, 0, 1, 0, 96];
+    data.extend_from_slice(b"MTrk");
+    data.extend_from_slice(&(track_length as u32).to_be_bytes());
+    data.extend_from_slice(&[0, 0xF0, 0x84, 0x80, 0x00]);
+    data.extend(std::iter::repeat_n(0x7F, sysex_length));
+    data.extend_from_slice(&[0, 0x90, 60, 100, 96, 0x80, 60, 64, 0, 0xFF, 0x2F, 0]);
+    let options = ImportOptions {
+        skip_payloads: true,
+        // Would be exceeded if the SysEx data was read
+        max_event_data: Some(16),
+        ..ImportOptions::default()
+    };
+    let mut smf = SMF::import_with_options(&mut &data[..], &options).unwrap();
+    let track = &mut smf.tracks[0];
+    assert_eq!(track.track_events.len(), 4);
+    match &track.track_events[0].event {
+        Event::SystemExclusiveF0(s) => {
+            assert!(s.data.is_empty());
+            assert_eq!(s.data.capacity(), 0);
+            assert_eq!(s.length.value, 0);
+        }
+        e => panic!("Expected a SysEx, got {:?}", e),
+    }
+    assert!(track.track_events[1].event.is_note_on());
+    // Lossy, but still a valid file once normalized
+    track.normalize().unwrap();
+    let exported = SMF::from_bytes(&smf.to_bytes().unwrap()).unwrap();
+    assert_eq!(exported.tracks[0].track_events.len(), 4);
+}
+
+#[test]
+fn abstract_round_trip() {
+    let smf = SMF::import(&mut &TWO_TRACKS[..]).unwrap();
+    let song = smf.to_abstract();
+    assert_eq!(song.tracks.len(), 2);
+    assert_eq!(song.tracks[1][3].0, 288);
+    let mut data = Vec::new();
+    SMF::from_abstract(song).unwrap().export(&mut data).unwrap();
+    assert_eq!(data, TWO_TRACKS.to_vec());
+}
+
+#[test]
+fn import_with_progress() {
+    let mut calls = Vec::new();
+    // No Seek needed
+    let smf = SMF::import_with_progress(
+        &mut &TWO_TRACKS[..],
+        TWO_TRACKS.len() as u64,
+        |read, total| calls.push((read, total)),
+    )
+    .unwrap();
+    assert_eq!(smf.tracks.len(), 2);
+    assert_eq!(calls, vec![(34, 63), (63, 63)]);
+}
+
+#[test]
+fn export_with_size() {
+    let mut smf = SMF::import(&mut &TWO_TRACKS[..]).unwrap();
+    for track in &mut smf.tracks {
+        track.normalize().unwrap();
+    }
+    let mut data = Vec::new();
+    let size = smf.export_with_size(&mut data).unwrap();
+    assert_eq!(size, data.len() as u64);
+    assert_eq!(size, 63);
+}
+
+#[test]
+fn computed_size() {
+    let mut smf = SMF::import(&mut &TWO_TRACKS[..]).unwrap();
+    for track in &mut smf.tracks {
+        track.normalize().unwrap();
+    }
+    smf.trailing_chunks.push((*b"XFIH", vec![0; 5]));
+    let size = smf.computed_size().unwrap();
+    assert_eq!(size, smf.to_bytes().unwrap().len() as u64);
+    assert_eq!(size, 63 + 13);
+}
+
+#[test]
+fn from_abstract_delta_too_large() {
+    let mut song = SMF::import(&mut &SINGLE_NOTE[..]).unwrap().to_abstract();
+    // Move the End of Track 2^29 ticks after the Note Off
+    song.tracks[0][2].0 += 1 << 29;
+    match SMF::from_abstract(song) {
+        Err(SMFError::DeltaTooLarge(2, d)) => assert_eq!(d, 1 << 29),
+        r => panic!("Expected DeltaTooLarge, got {:?}", r),
+    }
+}
+
+#[test]
+fn bytes_round_trip() {
+    let smf = SMF::from_bytes(&TWO_TRACKS).unwrap();
+    assert_eq!(smf.tracks.len(), 2);
+    let data = smf.to_bytes().unwrap();
+    assert_eq!(data, TWO_TRACKS.to_vec());
+    assert_eq!(SMF::from_bytes(&data).unwrap().tracks, smf.tracks);
+}
+
+#[test]
+fn running_status_round_trip() {
+    let data = [
+        b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 0, 0, 1, 0, 96, // Header
+        b'M', b'T', b'r', b'k', 0, 0, 0, 17, // Track
+        0, 0x90, 60, 100, // Note On
+        0, 64, 100, // Note On with Running Status
+        96, 60, 0, // Note Off as a Note On with a velocity of 0, Running Status
+        0, 64, 0, // Same
+        0, 0xFF, 0x2F, 0, // End of Track
+    ];
+    let smf = SMF::from_bytes(&data).unwrap();
+    assert_eq!(smf.tracks[0].length, 17);
+    let exported = smf.to_bytes().unwrap();
+    // Every event gets its status byte back
+    assert_eq!(exported.len(), data.len() + 3);
+    let imported = SMF::from_bytes(&exported).unwrap();
+    assert_eq!(imported.tracks[0].length, 20);
+    assert_eq!(imported.tracks[0].track_events, smf.tracks[0].track_events);
+}
+
+#[test]
+fn track_offsets() {
+    let options = ImportOptions {
+        track_offsets: true,
+        ..ImportOptions::default()
+    };
+    let smf = SMF::import_with_options(&mut &SINGLE_NOTE[..], &options).unwrap();
+    assert_eq!(smf.tracks[0].offsets, vec![22, 26, 30]);
+    // Offsets are only recorded when asked for
+    assert!(SMF::import(&mut &SINGLE_NOTE[..]).unwrap().tracks[0]
+        .offsets
+        .is_empty());
+    // The End of Track is cut short
+    match SMF::import_with_options(&mut &SINGLE_NOTE[..32], &options) {
+        Err(SMFError::AtOffset(30, e)) => {
+            assert_eq!(e.io_kind(), Some(std::io::ErrorKind::UnexpectedEof))
+        }
+        r => panic!("Expected AtOffset, got {:?}", r),
+    }
+}
+
+#[test]
+fn trailing_chunks() {
+    let mut data = SINGLE_NOTE.to_vec();
+    data.extend_from_slice(&[b'X', b'F', b'I', b'H', 0, 0, 0, 3, 1, 2, 3]);
+    let smf = SMF::from_bytes(&data).unwrap();
+    assert_eq!(smf.tracks.len(), 1);
+    assert_eq!(smf.trailing_chunks, vec![(*b"XFIH", vec![1, 2, 3])]);
+    assert_eq!(smf.to_bytes().unwrap(), data);
+    // Ids that are not ASCII are kept as is
+    let mut other = SINGLE_NOTE.to_vec();
+    other.extend_from_slice(&[0xE9, b'T', b'C', 0xFF, 0, 0, 0, 1, 7]);
+    let smf = SMF::from_bytes(&other).unwrap();
+    assert_eq!(
+        smf.trailing_chunks,
+        vec![([0xE9, b'T', b'C', 0xFF], vec![7])]
+    );
+    assert_eq!(smf.to_bytes().unwrap(), other);
+    // Padding, or a chunk cut short, at the end of the file is ignored
+    for end in [
+        &[0, 0][..],
+        &[b'X', b'F', b'I', b'H', 0, 0],
+        &[b'X', b'F', b'I', b'H', 0, 0, 0, 3, 1],
+    ] {
+        let mut padded = data.clone();
+        padded.extend_from_slice(end);
+        let smf = SMF::from_bytes(&padded).unwrap();
+        assert_eq!(smf.trailing_chunks.len(), 1);
+        assert_eq!(
+            smf.warnings,
+            vec![ImportWarning::TruncatedChunk {
+                offset: data.len() as u64
+            }]
+        );
+    }
+    // Another file following is not taken as a trailing chunk
+    data.extend_from_slice(&SINGLE_NOTE);
+    let smfs = SMF::import_all(&mut &data[..]).unwrap();
+    assert_eq!(smfs.len(), 2);
+    assert_eq!(smfs[0].trailing_chunks.len(), 1);
+    assert!(smfs[1].trailing_chunks.is_empty());
+}
+
+#[test]
+fn extra_tracks() {
+    // Claims a single track but contains two
+    let mut data = TWO_TRACKS.to_vec();
+    data[11] = 1;
+    let smf = SMF::from_bytes(&data).unwrap();
+    assert_eq!(smf.tracks.len(), 1);
+    assert_eq!(&smf.trailing_chunks[0].0, b"MTrk");
+    let options = ImportOptions {
+        read_extra_tracks: true,
+        ..ImportOptions::default()
+    };
+    let smf = SMF::import_with_options(&mut &data[..], &options).unwrap();
+    assert_eq!(smf.header.nb_tracks, 2);
+    assert_eq!(smf.tracks.len(), 2);
+    assert!(smf.trailing_chunks.is_empty());
+    assert_eq!(smf.to_bytes().unwrap(), TWO_TRACKS.to_vec());
+    // Junk at the end is ignored
+    let mut junk = data.clone();
+    junk.extend_from_slice(&[0xDE, 0xAD]);
+    let smf = SMF::import_with_options(&mut &junk[..], &options).unwrap();
+    assert_eq!(smf.tracks.len(), 2);
+    assert_eq!(smf.warnings.len(), 1);
+    // An extra track that can't be read is an error, unless asked otherwise
+    data.extend_from_slice(&[b'M', b'T', b'r', b'k', 0, 0, 0, 4, 0, 0xF1, 0, 0]);
+    assert!(SMF::import_with_options(&mut &data[..], &options).is_err());
+    let options = ImportOptions {
+        ignore_trailing_data: true,
+        ..options
+    };
+    let smf = SMF::import_with_options(&mut &data[..], &options).unwrap();
+    assert_eq!(smf.tracks.len(), 2);
+    // Limits still apply to the extra tracks
+    let options = ImportOptions {
+        max_track_bytes: Some(12),
+        ..options
+    };
+    match SMF::import_with_options(&mut &data[..], &options) {
+        Err(SMFError::LimitExceeded(21, 12)) => {}
+        r => panic!("Expected LimitExceeded, got {:?}", r),
+    }
+}
+
+#[test]
+fn to_text() {
+    let smf = SMF::from_bytes(&TWO_TRACKS).unwrap();
+    let mut text = Vec::new();
+    smf.to_text(&mut text).unwrap();
+    let text = String::from_utf8(text).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(
+        lines[..4],
+        [
+            "Format: MultipleTrack, Tracks: 2, Time Division: TicksPerQuarterNote(96)",
+            "Track 0",
+            "    0 (+0): Tempo 500000 (120 BPM)",
+            "    384 (+384): EndOfTrack",
+        ]
+    );
+    assert_eq!(lines[5], "    0 (+0): NoteOn ch1 C4");
+    assert_eq!(lines.len(), 10);
+}
+
+#[test]
+fn export_snapped() {
+    let mut jittered = SMF::from_bytes(&TWO_TRACKS).unwrap();
+    let mut other = jittered.clone();
+    // Move the Note Off of the first note a few ticks away on each side
+    jittered.tracks[1].track_events[1].delta_time.value = 93;
+    jittered.tracks[1].track_events[2].delta_time.value = 3;
+    other.tracks[1].track_events[1].delta_time.value = 98;
+    other.tracks[1].track_events[2].delta_time.value = 0;
+    let options = ExportOptions {
+        snap_grid: Some(24),
+    };
+    let mut data = Vec::new();
+    jittered.export_with_options(&mut data, &options).unwrap();
+    let mut other_data = Vec::new();
+    other
+        .export_with_options(&mut other_data, &options)
+        .unwrap();
+    assert_eq!(data, other_data);
+    assert_eq!(data, TWO_TRACKS.to_vec());
+}
+
+#[test]
+fn timed_event_stream() {
+    let smf = SMF::from_bytes(&TWO_TRACKS).unwrap();
+    let stream: Vec<(u64, usize, EventKind)> = smf
+        .timed_event_stream()
+        .map(|e| (e.tick, e.track, e.event.kind()))
+        .collect();
+    assert_eq!(
+        stream,
+        vec![
+            (0, 0, EventKind::Tempo),
+            (0, 1, EventKind::NoteOn),
+            (96, 1, EventKind::NoteOff),
+            (96, 1, EventKind::NoteOn),
+            (288, 1, EventKind::NoteOff),
+            (288, 1, EventKind::EndOfTrack),
+            (384, 0, EventKind::EndOfTrack),
+        ]
+    );
+}
+
+#[test]
+fn lenient_magic() {
+    let mut data = SINGLE_NOTE.to_vec();
+    data[..4].copy_from_slice(b"mthd");
+    data[14..18].copy_from_slice(b"MTRK");
+    assert!(SMF::from_bytes(&data).is_err());
+    let options = ImportOptions {
+        lenient_magic: true,
+        ..ImportOptions::default()
+    };
+    let smf = SMF::import_with_options(&mut &data[..], &options).unwrap();
+    assert_eq!(smf.tracks[0].track_events.len(), 3);
+    assert_eq!(
+        smf.warnings,
+        vec![
+            ImportWarning::LenientMagic {
+                offset: 0,
+                found: *b"mthd"
+            },
+            ImportWarning::LenientMagic {
+                offset: 14,
+                found: *b"MTRK"
+            },
+        ]
+    );
+    // Anything else is still rejected
+    data[..4].copy_from_slice(b"MTh ");
+    assert!(SMF::import_with_options(&mut &data[..], &options).is_err());
+    assert!(SMF::from_bytes(&SINGLE_NOTE).unwrap().warnings.is_empty());
+}
@@ -0,0 +1,407 @@
+use standard_midi_file::error::SMFError;
+use standard_midi_file::track::event::*;
+use standard_midi_file::track::*;
+use standard_midi_file::vlv::VLV;
+
+#[test]
+fn status_byte() {
+    let track_event = TrackEvent {
+        delta_time: VLV::new(0).unwrap(),
+        event: Event::NoteOn(NoteChange {
+            channel: 3,
+            key: 60,
+            velocity: 100,
+        }),
+    };
+    assert_eq!(track_event.status_byte(), Some(0x93));
+    let end = TrackEvent {
+        delta_time: VLV::new(0).unwrap(),
+        event: Event::EndOfTrack(EndOfTrack {}),
+    };
+    assert_eq!(end.status_byte(), None);
+}
+
+#[test]
+fn stored_track_events() {
+    let data = [
+        0, 0x93, 0x3C, 0x40, // Note On
+        0x60, 0x3C, 0, // Note On using Running Status
+        0x81, 0x00, 0xFF, 0x2F, 0, // End of Track
+    ];
+    let stored = StoredTrackEvents::new(&data)
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(stored.len(), 3);
+    assert_eq!(
+        stored.iter().map(|s| s.offset).collect::<Vec<_>>(),
+        vec![0, 4, 7]
+    );
+    assert_eq!(
+        stored.iter().map(|s| s.running_status).collect::<Vec<_>>(),
+        vec![false, true, false]
+    );
+    assert_eq!(stored[1].track_event.status_byte(), Some(0x93));
+    assert_eq!(stored[2].track_event.delta_time.value, 128);
+}
+
+fn track_event(delta_time: u32, event: Event) -> TrackEvent {
+    TrackEvent {
+        delta_time: VLV::new(delta_time).unwrap(),
+        event,
+    }
+}
+
+fn note_on(channel: u8, key: u8, velocity: u8) -> Event {
+    Event::NoteOn(NoteChange {
+        channel,
+        key,
+        velocity,
+    })
+}
+
+#[test]
+fn end_of_track_present() {
+    let mut track = SMFTrack {
+        length: 0,
+        track_events: vec![
+            track_event(0, note_on(0, 60, 100)),
+            track_event(10, Event::EndOfTrack(EndOfTrack {})),
+        ],
+        offsets: Vec::new(),
+    };
+    assert!(track.ends_properly());
+    let before = track.clone();
+    track.ensure_end_of_track().unwrap();
+    assert_eq!(track, before);
+}
+
+#[test]
+fn end_of_track_missing() {
+    let mut track = SMFTrack {
+        length: 0,
+        track_events: vec![track_event(0, note_on(0, 60, 100))],
+        offsets: Vec::new(),
+    };
+    assert!(!track.ends_properly());
+    track.ensure_end_of_track().unwrap();
+    assert!(track.ends_properly());
+    assert_eq!(track.track_events.len(), 2);
+    assert_eq!(track.track_events[1].delta_time.value, 0);
+}
+
+#[test]
+fn end_of_track_misplaced() {
+    let mut track = SMFTrack {
+        length: 0,
+        track_events: vec![
+            track_event(0, note_on(0, 60, 100)),
+            track_event(10, Event::EndOfTrack(EndOfTrack {})),
+            track_event(20, note_on(0, 60, 0)),
+        ],
+        offsets: Vec::new(),
+    };
+    assert!(!track.ends_properly());
+    track.ensure_end_of_track().unwrap();
+    assert!(track.ends_properly());
+    assert_eq!(track.track_events.len(), 3);
+    // The Note Off is still at tick 30
+    assert_eq!(track.track_events[1].delta_time.value, 30);
+    assert_eq!(track.track_events[1].event, note_on(0, 60, 0));
+    // Carrying a Delta-Time too large to be written fails, leaving the track as it was
+    let mut far = SMFTrack {
+        length: 0,
+        track_events: vec![
+            track_event(0x0FFF_FFFF, Event::EndOfTrack(EndOfTrack {})),
+            track_event(0x0FFF_FFFF, note_on(0, 60, 100)),
+        ],
+        offsets: Vec::new(),
+    };
+    let before = far.clone();
+    match far.ensure_end_of_track() {
+        Err(SMFError::DeltaTooLarge(1, d)) => assert_eq!(d, 0x1FFF_FFFE),
+        r => panic!("Expected DeltaTooLarge, got {:?}", r),
+    }
+    assert_eq!(far, before);
+}
+
+#[test]
+fn for_each_event_mut() {
+    let mut track = SMFTrack {
+        length: 12,
+        track_events: vec![
+            track_event(0, note_on(0, 60, 100)),
+            track_event(10, note_on(1, 64, 50)),
+            track_event(10, Event::EndOfTrack(EndOfTrack {})),
+        ],
+        offsets: Vec::new(),
+    };
+    track.for_each_event_mut(|event| {
+        if let Event::NoteOn(n) = event {
+            n.velocity /= 2;
+        }
+    });
+    track.normalize().unwrap();
+    assert_eq!(track.track_events[0].event, note_on(0, 60, 50));
+    assert_eq!(track.track_events[1].event, note_on(1, 64, 25));
+    assert_eq!(track.length, 12);
+}
+
+#[test]
+fn humanize() {
+    let mut track = SMFTrack {
+        length: 0,
+        track_events: vec![
+            track_event(0, note_on(0, 60, 100)),
+            track_event(96, note_on(0, 60, 0)),
+            track_event(0, note_on(0, 62, 100)),
+            track_event(96, note_on(0, 62, 0)),
+            track_event(0, Event::EndOfTrack(EndOfTrack {})),
+        ],
+        offsets: Vec::new(),
+    };
+    let original: Vec<u64> = track.iter_absolute().map(|(t, _)| t).collect();
+    let mut other = track.clone();
+    track.humanize(10, 42);
+    other.humanize(10, 42);
+    assert_eq!(track, other);
+    let ticks: Vec<u64> = track.iter_absolute().map(|(t, _)| t).collect();
+    for (new, old) in ticks.iter().zip(&original) {
+        assert!((*new as i64 - *old as i64).abs() <= 10);
+    }
+    // Note Offs and End of Track do not move
+    assert_eq!(ticks[1], 96);
+    assert_eq!(ticks[3], 192);
+    assert_eq!(ticks[4], 192);
+}
+
+#[test]
+fn swing() {
+    let mut track = SMFTrack {
+        length: 0,
+        track_events: vec![
+            track_event(2, note_on(0, 60, 100)),
+            track_event(38, note_on(0, 60, 0)),
+            track_event(10, note_on(0, 62, 100)),
+            track_event(40, note_on(0, 62, 0)),
+            track_event(5, note_on(0, 64, 100)),
+            track_event(45, note_on(0, 64, 0)),
+            track_event(0, Event::EndOfTrack(EndOfTrack {})),
+        ],
+        offsets: Vec::new(),
+    };
+    track.swing(48, 0.66);
+    let ticks: Vec<u64> = track.iter_absolute().map(|(t, _)| t).collect();
+    // On-beats snap to the grid, the off-beat is pushed from 48 to 63, Note Offs do not move
+    assert_eq!(ticks, vec![0, 40, 63, 90, 96, 140, 140]);
+    // Notes are not moved past the next event
+    let mut track = SMFTrack {
+        length: 0,
+        track_events: vec![
+            track_event(48, note_on(0, 60, 100)),
+            track_event(8, note_on(0, 60, 0)),
+            track_event(0, Event::EndOfTrack(EndOfTrack {})),
+        ],
+        offsets: Vec::new(),
+    };
+    track.swing(48, 0.66);
+    let ticks: Vec<u64> = track.iter_absolute().map(|(t, _)| t).collect();
+    assert_eq!(ticks, vec![56, 56, 56]);
+}
+
+#[test]
+fn running_status_after_meta() {
+    let data = [
+        b'M', b'T', b'r', b'k', 0, 0, 0, 15, // Track
+        0, 0x90, 0x3C, 0x40, // Note On
+        0, 0xFF, 0x01, 1, b'a', // Text
+        0, 0x3C, 0x00, // Data byte, no Running Status to use
+        0, 0xFF, 0x2F, 0, // End of Track
+    ];
+    match SMFTrack::import(&mut &data[..]) {
+        Err(SMFError::NoPreviousEvent) => {}
+        Err(e) => panic!("Unexpected error: {}", e),
+        Ok(_) => panic!("Running Status was used after a Meta Event"),
+    }
+    let stored: Vec<_> = StoredTrackEvents::new(&data[8..]).collect();
+    assert!(stored[2].is_err());
+}
+
+#[test]
+fn builder_melody() {
+    let mut builder = builder::TrackBuilder::new();
+    builder.note(0, 60, 100, 0, 96).note(0, 62, 90, 96, 48);
+    let track = builder.build().unwrap();
+    let deltas: Vec<u32> = track
+        .track_events
+        .iter()
+        .map(|t| t.delta_time.value)
+        .collect();
+    assert_eq!(deltas, vec![0, 96, 0, 48, 0]);
+    assert_eq!(track.track_events[0].event, note_on(0, 60, 100));
+    match track.track_events[1].event {
+        Event::NoteOff(n) => assert_eq!(n.key, 60),
+        ref e => panic!("Expected a Note Off, got {:?}", e),
+    }
+    assert_eq!(track.track_events[2].event, note_on(0, 62, 90));
+    assert!(track.ends_properly());
+}
+
+#[test]
+fn truncated_text_event() {
+    let data = [
+        b'M', b'T', b'r', b'k', 0, 0, 0, 12, // Track
+        0, 0xFF, 0x01, 0x7F, b'H', b'i', b'!', // Text claiming 127 bytes
+        0, 0xFF, 0x2F, 0, // End of Track
+    ];
+    match SMFTrack::import(&mut &data[..]) {
+        Err(SMFError::TruncatedEvent(127, 12)) => {}
+        r => panic!("Expected TruncatedEvent, got {:?}", r),
+    }
+}
+
+#[test]
+fn normalize_note_offs() {
+    let note = |velocity| NoteChange {
+        channel: 0,
+        key: 60,
+        velocity,
+    };
+    let mut track = SMFTrack {
+        length: 0,
+        track_events: vec![
+            Event::NoteOn(note(100)),
+            Event::NoteOn(note(0)),
+            Event::NoteOn(note(100)),
+            Event::NoteOff(note(30)),
+        ]
+        .into_iter()
+        .map(|event| TrackEvent {
+            delta_time: VLV::new(48).unwrap(),
+            event,
+        })
+        .collect(),
+        offsets: Vec::new(),
+    };
+    track.normalize_note_offs(true);
+    let events: Vec<&Event> = track.track_events.iter().map(|t| &t.event).collect();
+    assert_eq!(events[1], &Event::NoteOff(note(64)));
+    assert_eq!(events[3], &Event::NoteOff(note(30)));
+    track.normalize_note_offs(false);
+    assert_eq!(track.track_events[1].event, Event::NoteOn(note(0)));
+    assert_eq!(track.track_events[3].event, Event::NoteOn(note(0)));
+    assert_eq!(track.track_events[2].event, Event::NoteOn(note(100)));
+}
+
+#[test]
+fn coalesce_text() {
+    let lyric = |text: &str| TrackEvent {
+        delta_time: VLV::new(0).unwrap(),
+        event: Event::Lyric(TextMessage::new(text).unwrap()),
+    };
+    let mut later = lyric("Oh");
+    later.delta_time = VLV::new(96).unwrap();
+    let mut track = SMFTrack {
+        length: 0,
+        track_events: vec![lyric("Hel"), lyric("lo "), lyric("world"), later],
+        offsets: Vec::new(),
+    };
+    track.coalesce_text(EventKind::Lyric).unwrap();
+    assert_eq!(track.track_events.len(), 2);
+    assert_eq!(track.track_events[0], lyric("Hello world"));
+    assert_eq!(
+        track.track_events[0]
+            .event
+            .text_message()
+            .unwrap()
+            .length
+            .value,
+        11
+    );
+    assert_eq!(track.length, track.computed_length().unwrap());
+    // Other kinds are not merged
+    track.coalesce_text(EventKind::Marker).unwrap();
+    assert_eq!(track.track_events.len(), 2);
+}
+
+#[test]
+fn delta_too_large() {
+    let gap = 1 << 29;
+    let mut track = SMFTrack {
+        length: 0,
+        track_events: vec![TrackEvent {
+            delta_time: {
+                let mut delta_time = VLV::new(0).unwrap();
+                delta_time.value = gap;
+                delta_time
+            },
+            event: Event::EndOfTrack(EndOfTrack {}),
+        }],
+        offsets: Vec::new(),
+    };
+    match track.normalize() {
+        Err(SMFError::DeltaTooLarge(0, d)) => assert_eq!(d, u64::from(gap)),
+        r => panic!("Expected DeltaTooLarge, got {:?}", r),
+    }
+}
+
+#[test]
+fn raw_track() {
+    let data = [
+        b'M', b'T', b'r', b'k', 0, 0, 0, 11, // Track
+        0, 0x90, 0x3C, 0x40, // Note On
+        0x60, 0x3C, 0x00, // Note Off, using Running Status
+        0, 0xFF, 0x2F, 0, // End of Track
+    ];
+    let raw = SMFTrack::import_raw(&mut &data[..]).unwrap();
+    assert_eq!(raw.data, data[8..].to_vec());
+    let mut exported = Vec::new();
+    raw.export(&mut exported).unwrap();
+    assert_eq!(exported, data.to_vec());
+    let track = raw.parse().unwrap();
+    assert_eq!(track.track_events.len(), 3);
+    assert_eq!(
+        raw.stored_events()
+            .filter(|e| e.as_ref().unwrap().running_status)
+            .count(),
+        1
+    );
+    assert!(SMFTrack::import_raw(&mut &data[..12]).is_err());
+}
+
+#[test]
+fn stream_events() {
+    let data = [
+        0x00, 0x90, 0x3C, 0x64, // Note On
+        0x60, 0x3C, 0x00, // Running Status Note Off
+        0x00, 0xFF, 0x2F, 0x00, // End of Track
+        0xAA, 0xBB, // Next chunk, not part of the track
+    ];
+    let mut events = SMFTrack::stream_events(&data[..], 11);
+    let first = events.next().unwrap().unwrap();
+    assert!(first.event.is_note_on());
+    assert_eq!(events.count(), 2);
+    // An error ends the iteration
+    let events: Vec<_> = SMFTrack::stream_events(&data[4..], 7).collect();
+    assert_eq!(events.len(), 1);
+    assert!(matches!(events[0], Err(SMFError::NoPreviousEvent)));
+}
+
+#[test]
+fn duration_ticks() {
+    let track = SMFTrack {
+        length: 0,
+        track_events: vec![
+            track_event(10, note_on(0, 60, 100)),
+            track_event(96, note_on(0, 60, 0)),
+            track_event(0x0FFF_FFFF, Event::EndOfTrack(EndOfTrack {})),
+        ],
+        offsets: Vec::new(),
+    };
+    assert_eq!(track.duration_ticks(), 10 + 96 + 0x0FFF_FFFF);
+    let empty = SMFTrack {
+        length: 0,
+        track_events: Vec::new(),
+        offsets: Vec::new(),
+    };
+    assert_eq!(empty.duration_ticks(), 0);
+}
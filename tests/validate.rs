@@ -0,0 +1,95 @@
+mod common;
+
+use common::*;
+use standard_midi_file::header::Format;
+use standard_midi_file::track::event::{Event, SequenceNumber, SystemExclusive, TextMessage};
+use standard_midi_file::validate::ValidationIssue;
+use standard_midi_file::vlv::VLV;
+
+fn sysex(data: &[u8]) -> SystemExclusive {
+    SystemExclusive {
+        length: VLV::new(data.len() as u32).unwrap(),
+        data: data.to_vec(),
+    }
+}
+
+#[test]
+fn unterminated_sysex() {
+    let smf = smf(
+        Format::MultipleTrack,
+        vec![
+            // Split in two packets, properly terminated
+            track(vec![
+                (0, Event::SystemExclusiveF0(sysex(&[0x41, 0x10]))),
+                (10, Event::SystemExclusiveF7(sysex(&[0x42, 0xF7]))),
+                (0, end_of_track()),
+            ]),
+            // The second packet never comes
+            track(vec![
+                (0, Event::SystemExclusiveF0(sysex(&[0x7E, 0x7F, 0xF7]))),
+                (48, Event::SystemExclusiveF0(sysex(&[0x41, 0x10]))),
+                (96, end_of_track()),
+            ]),
+        ],
+    );
+    assert_eq!(
+        smf.validate(),
+        vec![ValidationIssue::UnterminatedSysEx { track: 1, tick: 48 }]
+    );
+}
+
+#[test]
+fn suggested_format() {
+    let song = || {
+        track(vec![
+            (0, note_on(0, 60, 100)),
+            (96, note_off(0, 60)),
+            (0, end_of_track()),
+        ])
+    };
+    let mut smf = smf(Format::SingleTrack, vec![song(), song()]);
+    assert_eq!(smf.suggested_format(), Format::MultipleTrack);
+    assert_eq!(
+        smf.validate(),
+        vec![ValidationIssue::FormatMismatch {
+            declared: Format::SingleTrack,
+            suggested: Format::MultipleTrack
+        }]
+    );
+    smf.header.format = Format::MultipleSong;
+    assert!(smf.validate().is_empty());
+    smf.tracks.pop();
+    assert_eq!(smf.suggested_format(), Format::SingleTrack);
+}
+
+#[test]
+fn misplaced_sequence_number() {
+    let sequence_number = |n| Event::SequenceNumber(SequenceNumber { sequence_number: n });
+    let smf = smf(
+        Format::MultipleSong,
+        vec![
+            track(vec![
+                (
+                    0,
+                    Event::SequenceTrackName(TextMessage::new("Song").unwrap()),
+                ),
+                (0, sequence_number(1)),
+                (0, note_on(0, 60, 100)),
+                (96, note_off(0, 60)),
+                (0, end_of_track()),
+            ]),
+            track(vec![
+                (0, note_on(0, 60, 100)),
+                (96, sequence_number(2)),
+                (0, note_off(0, 60)),
+                (0, end_of_track()),
+            ]),
+        ],
+    );
+    assert_eq!(smf.tracks[0].sequence_number(), Some(1));
+    assert_eq!(smf.tracks[1].sequence_number(), None);
+    assert_eq!(
+        smf.validate(),
+        vec![ValidationIssue::MisplacedSequenceNumber { track: 1, tick: 96 }]
+    );
+}
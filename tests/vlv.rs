@@ -112,3 +112,39 @@ fn too_long() {
         },
     }
 }
+
+#[test]
+fn padded_round_trip() {
+    let padded = [0x80, 0x80, 0x05];
+    let my_vlv = VLV::import(&mut Cursor::new(padded)).unwrap();
+    assert_eq!(my_vlv.value, 5);
+    assert_eq!(my_vlv.get_length().unwrap(), 3);
+    assert_eq!(my_vlv.padded_length(), Some(3));
+    assert_eq!(my_vlv, VLV::new(5).unwrap());
+    let mut data = Vec::new();
+    my_vlv.export(&mut data).unwrap();
+    assert_eq!(data, padded.to_vec());
+    // A VLV created from a value is still minimal
+    let mut data = Vec::new();
+    VLV::new(5).unwrap().export(&mut data).unwrap();
+    assert_eq!(data, vec![0x05]);
+}
+
+#[test]
+fn with_width() {
+    let vlv = VLV::with_width(5, 3).unwrap();
+    assert_eq!(vlv.padded_length(), Some(3));
+    let mut data = Vec::new();
+    vlv.export(&mut data).unwrap();
+    assert_eq!(data, vec![0x80, 0x80, 0x05]);
+    // No padding is needed at the minimal width
+    assert_eq!(VLV::with_width(200, 2).unwrap().padded_length(), None);
+    assert!(matches!(
+        VLV::with_width(200, 1),
+        Err(SMFError::VLV(VLVError::NumberTooBig(200)))
+    ));
+    assert!(matches!(
+        VLV::with_width(5, 5),
+        Err(SMFError::VLV(VLVError::VLVTooBig))
+    ));
+}